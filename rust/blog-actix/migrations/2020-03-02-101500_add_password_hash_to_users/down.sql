@@ -0,0 +1,8 @@
+CREATE TABLE users_without_passwords (
+    id INTEGER PRIMARY KEY NOT NULL,
+    username VARCHAR NOT NULL
+);
+INSERT INTO users_without_passwords (id, username) SELECT id, username FROM users;
+DROP TABLE users;
+ALTER TABLE users_without_passwords RENAME TO users;
+CREATE UNIQUE INDEX username_unique_idx ON users (username);
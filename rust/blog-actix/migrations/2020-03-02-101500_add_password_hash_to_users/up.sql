@@ -0,0 +1,3 @@
+-- Users created before passwords existed get an empty hash, which never verifies, so they
+-- cannot log in until a password is set.
+ALTER TABLE users ADD COLUMN password_hash VARCHAR NOT NULL DEFAULT '';
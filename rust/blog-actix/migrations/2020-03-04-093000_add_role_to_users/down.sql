@@ -0,0 +1,10 @@
+CREATE TABLE users_without_roles (
+    id INTEGER PRIMARY KEY NOT NULL,
+    username VARCHAR NOT NULL,
+    password_hash VARCHAR NOT NULL DEFAULT ''
+);
+INSERT INTO users_without_roles (id, username, password_hash)
+    SELECT id, username, password_hash FROM users;
+DROP TABLE users;
+ALTER TABLE users_without_roles RENAME TO users;
+CREATE UNIQUE INDEX username_unique_idx ON users (username);
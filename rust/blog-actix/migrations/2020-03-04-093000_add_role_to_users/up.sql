@@ -0,0 +1,3 @@
+-- One of 'author', 'moderator' or 'admin'. The first admin has to be appointed here, e.g.
+-- UPDATE users SET role = 'admin' WHERE username = '...';
+ALTER TABLE users ADD COLUMN role VARCHAR NOT NULL DEFAULT 'author';
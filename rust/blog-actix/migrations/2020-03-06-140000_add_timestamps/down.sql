@@ -0,0 +1,34 @@
+CREATE TABLE users_without_timestamps (
+    id INTEGER PRIMARY KEY NOT NULL,
+    username VARCHAR NOT NULL,
+    password_hash VARCHAR NOT NULL DEFAULT '',
+    role VARCHAR NOT NULL DEFAULT 'author'
+);
+INSERT INTO users_without_timestamps (id, username, password_hash, role)
+    SELECT id, username, password_hash, role FROM users;
+DROP TABLE users;
+ALTER TABLE users_without_timestamps RENAME TO users;
+CREATE UNIQUE INDEX username_unique_idx ON users (username);
+
+CREATE TABLE posts_without_timestamps (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users (id),
+    title VARCHAR NOT NULL,
+    body TEXT NOT NULL,
+    published BOOLEAN NOT NULL DEFAULT 0
+);
+INSERT INTO posts_without_timestamps (id, user_id, title, body, published)
+    SELECT id, user_id, title, body, published FROM posts;
+DROP TABLE posts;
+ALTER TABLE posts_without_timestamps RENAME TO posts;
+
+CREATE TABLE comments_without_timestamps (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users (id),
+    post_id INTEGER NOT NULL REFERENCES posts (id),
+    body TEXT NOT NULL
+);
+INSERT INTO comments_without_timestamps (id, user_id, post_id, body)
+    SELECT id, user_id, post_id, body FROM comments;
+DROP TABLE comments;
+ALTER TABLE comments_without_timestamps RENAME TO comments;
@@ -0,0 +1,12 @@
+CREATE TABLE comments_without_deleted_at (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users (id),
+    post_id INTEGER NOT NULL REFERENCES posts (id),
+    body TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00',
+    updated_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00'
+);
+INSERT INTO comments_without_deleted_at (id, user_id, post_id, body, created_at, updated_at)
+    SELECT id, user_id, post_id, body, created_at, updated_at FROM comments WHERE deleted_at IS NULL;
+DROP TABLE comments;
+ALTER TABLE comments_without_deleted_at RENAME TO comments;
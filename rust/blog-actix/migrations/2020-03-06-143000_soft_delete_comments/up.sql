@@ -0,0 +1,3 @@
+-- A deleted comment keeps its row, so that replies and counts still make sense, but is no
+-- longer shown.
+ALTER TABLE comments ADD COLUMN deleted_at TIMESTAMP;
@@ -0,0 +1,29 @@
+-- External content tables: the text lives in `posts` and `comments`, and these only hold the
+-- index. The triggers below keep the index in step with every change to the source rows.
+CREATE VIRTUAL TABLE posts_fts USING fts5(title, body, content='posts', content_rowid='id');
+CREATE VIRTUAL TABLE comments_fts USING fts5(body, content='comments', content_rowid='id');
+
+INSERT INTO posts_fts (rowid, title, body) SELECT id, title, body FROM posts;
+INSERT INTO comments_fts (rowid, body) SELECT id, body FROM comments;
+
+CREATE TRIGGER posts_fts_insert AFTER INSERT ON posts BEGIN
+  INSERT INTO posts_fts (rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
+CREATE TRIGGER posts_fts_delete AFTER DELETE ON posts BEGIN
+  INSERT INTO posts_fts (posts_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+END;
+CREATE TRIGGER posts_fts_update AFTER UPDATE OF title, body ON posts BEGIN
+  INSERT INTO posts_fts (posts_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+  INSERT INTO posts_fts (rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
+
+CREATE TRIGGER comments_fts_insert AFTER INSERT ON comments BEGIN
+  INSERT INTO comments_fts (rowid, body) VALUES (new.id, new.body);
+END;
+CREATE TRIGGER comments_fts_delete AFTER DELETE ON comments BEGIN
+  INSERT INTO comments_fts (comments_fts, rowid, body) VALUES ('delete', old.id, old.body);
+END;
+CREATE TRIGGER comments_fts_update AFTER UPDATE OF body ON comments BEGIN
+  INSERT INTO comments_fts (comments_fts, rowid, body) VALUES ('delete', old.id, old.body);
+  INSERT INTO comments_fts (rowid, body) VALUES (new.id, new.body);
+END;
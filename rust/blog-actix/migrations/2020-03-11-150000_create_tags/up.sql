@@ -0,0 +1,12 @@
+CREATE TABLE tags (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name VARCHAR NOT NULL
+);
+CREATE UNIQUE INDEX tag_name_unique_idx ON tags (name);
+
+CREATE TABLE post_tags (
+    post_id INTEGER NOT NULL REFERENCES posts (id),
+    tag_id INTEGER NOT NULL REFERENCES tags (id),
+    PRIMARY KEY (post_id, tag_id)
+);
+CREATE INDEX post_tags_tag_id_idx ON post_tags (tag_id);
@@ -0,0 +1,25 @@
+CREATE TABLE comments_without_parent_id (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users (id),
+    post_id INTEGER NOT NULL REFERENCES posts (id),
+    body TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00',
+    updated_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00',
+    deleted_at TIMESTAMP
+);
+INSERT INTO comments_without_parent_id (id, user_id, post_id, body, created_at, updated_at, deleted_at)
+    SELECT id, user_id, post_id, body, created_at, updated_at, deleted_at FROM comments;
+DROP TABLE comments;
+ALTER TABLE comments_without_parent_id RENAME TO comments;
+
+-- Dropping the table took the search index triggers with it.
+CREATE TRIGGER comments_fts_insert AFTER INSERT ON comments BEGIN
+  INSERT INTO comments_fts (rowid, body) VALUES (new.id, new.body);
+END;
+CREATE TRIGGER comments_fts_delete AFTER DELETE ON comments BEGIN
+  INSERT INTO comments_fts (comments_fts, rowid, body) VALUES ('delete', old.id, old.body);
+END;
+CREATE TRIGGER comments_fts_update AFTER UPDATE OF body ON comments BEGIN
+  INSERT INTO comments_fts (comments_fts, rowid, body) VALUES ('delete', old.id, old.body);
+  INSERT INTO comments_fts (rowid, body) VALUES (new.id, new.body);
+END;
@@ -0,0 +1,3 @@
+-- A reply points at the comment it answers. Top level comments have no parent.
+ALTER TABLE comments ADD COLUMN parent_id INTEGER REFERENCES comments (id);
+CREATE INDEX comments_parent_id_idx ON comments (parent_id);
@@ -0,0 +1,26 @@
+DROP TABLE reviews;
+
+CREATE TABLE posts_with_published (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users (id),
+    title VARCHAR NOT NULL,
+    body TEXT NOT NULL,
+    published BOOLEAN NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00',
+    updated_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00'
+);
+INSERT INTO posts_with_published (id, user_id, title, body, published, created_at, updated_at)
+    SELECT id, user_id, title, body, status = 'published', created_at, updated_at FROM posts;
+DROP TABLE posts;
+ALTER TABLE posts_with_published RENAME TO posts;
+
+CREATE TRIGGER posts_fts_insert AFTER INSERT ON posts BEGIN
+  INSERT INTO posts_fts (rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
+CREATE TRIGGER posts_fts_delete AFTER DELETE ON posts BEGIN
+  INSERT INTO posts_fts (posts_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+END;
+CREATE TRIGGER posts_fts_update AFTER UPDATE OF title, body ON posts BEGIN
+  INSERT INTO posts_fts (posts_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+  INSERT INTO posts_fts (rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
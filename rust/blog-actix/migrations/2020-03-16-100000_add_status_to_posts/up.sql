@@ -0,0 +1,40 @@
+-- `status` takes over from `published`, which only told a draft from a published post.
+CREATE TABLE posts_with_status (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users (id),
+    title VARCHAR NOT NULL,
+    body TEXT NOT NULL,
+    status VARCHAR NOT NULL DEFAULT 'draft',
+    created_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00',
+    updated_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00'
+);
+INSERT INTO posts_with_status (id, user_id, title, body, status, created_at, updated_at)
+    SELECT id, user_id, title, body, CASE WHEN published THEN 'published' ELSE 'draft' END,
+        created_at, updated_at
+    FROM posts;
+DROP TABLE posts;
+ALTER TABLE posts_with_status RENAME TO posts;
+CREATE INDEX posts_status_idx ON posts (status);
+
+-- Dropping the table took the search index triggers with it.
+CREATE TRIGGER posts_fts_insert AFTER INSERT ON posts BEGIN
+  INSERT INTO posts_fts (rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
+CREATE TRIGGER posts_fts_delete AFTER DELETE ON posts BEGIN
+  INSERT INTO posts_fts (posts_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+END;
+CREATE TRIGGER posts_fts_update AFTER UPDATE OF title, body ON posts BEGIN
+  INSERT INTO posts_fts (posts_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+  INSERT INTO posts_fts (rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
+
+-- Every approval or rejection, with what the reviewer had to say.
+CREATE TABLE reviews (
+    id INTEGER PRIMARY KEY NOT NULL,
+    post_id INTEGER NOT NULL REFERENCES posts (id),
+    reviewer_id INTEGER NOT NULL REFERENCES users (id),
+    approved BOOLEAN NOT NULL,
+    note TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00'
+);
+CREATE INDEX reviews_post_id_idx ON reviews (post_id);
@@ -0,0 +1,13 @@
+CREATE TABLE users_without_token_version (
+    id INTEGER PRIMARY KEY NOT NULL,
+    username VARCHAR NOT NULL,
+    password_hash VARCHAR NOT NULL DEFAULT '',
+    role VARCHAR NOT NULL DEFAULT 'author',
+    created_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00',
+    updated_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00'
+);
+INSERT INTO users_without_token_version (id, username, password_hash, role, created_at, updated_at)
+    SELECT id, username, password_hash, role, created_at, updated_at FROM users;
+DROP TABLE users;
+ALTER TABLE users_without_token_version RENAME TO users;
+CREATE UNIQUE INDEX username_unique_idx ON users (username);
@@ -0,0 +1,18 @@
+-- SQLite hands the highest free id to the next row unless the key is AUTOINCREMENT, so a new
+-- user could take over the id of one just deleted, along with whatever still points at it.
+CREATE TABLE users_with_token_version (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    username VARCHAR NOT NULL,
+    password_hash VARCHAR NOT NULL DEFAULT '',
+    role VARCHAR NOT NULL DEFAULT 'author',
+    created_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00',
+    updated_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00',
+    -- Part of every token, and bumped to revoke the tokens issued so far, e.g. when the
+    -- password changes.
+    token_version INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO users_with_token_version (id, username, password_hash, role, created_at, updated_at)
+    SELECT id, username, password_hash, role, created_at, updated_at FROM users;
+DROP TABLE users;
+ALTER TABLE users_with_token_version RENAME TO users;
+CREATE UNIQUE INDEX username_unique_idx ON users (username);
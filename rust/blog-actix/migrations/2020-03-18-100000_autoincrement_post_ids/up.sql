@@ -0,0 +1,29 @@
+-- As with users, a new post would otherwise take the highest free id, and so could take over
+-- the id of one just deleted along with its `/post/{id}` link, which the feeds use as the id of
+-- their entries.
+CREATE TABLE posts_with_autoincrement (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users (id),
+    title VARCHAR NOT NULL,
+    body TEXT NOT NULL,
+    status VARCHAR NOT NULL DEFAULT 'draft',
+    created_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00',
+    updated_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00'
+);
+INSERT INTO posts_with_autoincrement (id, user_id, title, body, status, created_at, updated_at)
+    SELECT id, user_id, title, body, status, created_at, updated_at FROM posts;
+DROP TABLE posts;
+ALTER TABLE posts_with_autoincrement RENAME TO posts;
+CREATE INDEX posts_status_idx ON posts (status);
+
+-- Dropping the table took the search index triggers with it.
+CREATE TRIGGER posts_fts_insert AFTER INSERT ON posts BEGIN
+  INSERT INTO posts_fts (rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
+CREATE TRIGGER posts_fts_delete AFTER DELETE ON posts BEGIN
+  INSERT INTO posts_fts (posts_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+END;
+CREATE TRIGGER posts_fts_update AFTER UPDATE OF title, body ON posts BEGIN
+  INSERT INTO posts_fts (posts_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+  INSERT INTO posts_fts (rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
@@ -0,0 +1 @@
+-- The characters which were taken out cannot be put back.
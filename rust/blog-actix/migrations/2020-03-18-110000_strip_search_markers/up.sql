@@ -0,0 +1,8 @@
+-- Search marks matches with char(2) and char(3), so text may no longer contain them. The
+-- update triggers bring the search index along.
+UPDATE posts SET
+    title = replace(replace(title, char(2), ''), char(3), ''),
+    body = replace(replace(body, char(2), ''), char(3), '')
+WHERE instr(title, char(2)) OR instr(title, char(3)) OR instr(body, char(2)) OR instr(body, char(3));
+UPDATE comments SET body = replace(replace(body, char(2), ''), char(3), '')
+WHERE instr(body, char(2)) OR instr(body, char(3));
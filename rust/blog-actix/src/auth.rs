@@ -0,0 +1,155 @@
+use crate::errors::AppError;
+use crate::models::{self, User};
+use crate::Pool;
+use actix_web::dev::Payload;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, FromRequest, HttpRequest};
+use diesel::prelude::*;
+use futures::{future, Future};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How long a token issued at login stays valid, in seconds.
+const TOKEN_LIFETIME: u64 = 60 * 60 * 24;
+
+/// The shortest secret key tokens may be signed with, in bytes, which is the size of the
+/// SHA-256 output the HMAC produces.
+pub const MIN_KEY_LEN: usize = 32;
+
+pub fn hash_password(password: &str) -> Result<String, AppError> {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    argon2::hash_encoded(password.as_bytes(), &salt, &argon2::Config::default())
+        .map_err(AppError::PasswordHashError)
+}
+
+// A hash in the form `hash_password` produces, with the same parameters, made from random
+// bytes rather than from any password.
+const DUMMY_HASH: &str = "$argon2i$v=19$m=4096,t=3,p=1$Au1c+s82EdAzdg9sFjePNg$\
+                          2HlaWpNZhhfvegSbeE7Cs2sFvZGP69oFY6k9b5aqL7M";
+
+// The encoded hash carries its own salt and parameters, so it is all we need to check a
+// password against it. An empty hash belongs to a user who has never set a password.
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    if hash.is_empty() {
+        return reject_password(password);
+    }
+    argon2::verify_encoded(hash, password.as_bytes()).unwrap_or(false)
+}
+
+// Always fails, but only after as long as checking a real hash takes, so that the time a
+// failed login takes does not tell whether the user exists.
+pub fn reject_password(password: &str) -> bool {
+    let _ = argon2::verify_encoded(DUMMY_HASH, password.as_bytes());
+    false
+}
+
+/// Issues and checks the session tokens handed out at login.
+///
+/// A token is `user_id.token_version.expires.signature`, where the signature is an
+/// HMAC-SHA256 of the rest under the server's secret key. Nothing is stored, so a token stays
+/// valid until it expires, the user's token version is bumped or the key changes.
+#[derive(Clone)]
+pub struct Tokens {
+    key: Arc<Vec<u8>>,
+}
+
+impl Tokens {
+    /// Fails if the key is too short to keep tokens from being guessed.
+    pub fn new(key: &str) -> std::io::Result<Self> {
+        if key.len() < MIN_KEY_LEN {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("the secret key must be at least {} bytes", MIN_KEY_LEN),
+            ));
+        }
+        Ok(Tokens {
+            key: Arc::new(key.as_bytes().to_vec()),
+        })
+    }
+
+    pub fn issue(&self, user_id: i32, token_version: i32) -> String {
+        let expires = now() + TOKEN_LIFETIME;
+        let claims = format!("{}.{}.{}", user_id, token_version, expires);
+        let signature = hex::encode(self.mac(&claims).result().code());
+        format!("{}.{}", claims, signature)
+    }
+
+    /// Returns the id and token version of the user a token was issued to, if it is genuine and
+    /// has not expired.
+    pub fn verify(&self, token: &str) -> Option<(i32, i32)> {
+        let mut parts = token.rsplitn(2, '.');
+        let signature = hex::decode(parts.next()?).ok()?;
+        let claims = parts.next()?;
+        self.mac(claims).verify(&signature).ok()?;
+
+        let mut claims = claims.splitn(3, '.');
+        let user_id = claims.next()?.parse().ok()?;
+        let token_version = claims.next()?.parse().ok()?;
+        let expires: u64 = claims.next()?.parse().ok()?;
+        if expires < now() {
+            return None;
+        }
+        Some((user_id, token_version))
+    }
+
+    fn mac(&self, claims: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_varkey(&self.key).expect("HMAC accepts any key length");
+        mac.input(claims.as_bytes());
+        mac
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    let value = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    if value.len() > 7 && value[..7].eq_ignore_ascii_case("bearer ") {
+        Some(value[7..].trim())
+    } else {
+        None
+    }
+}
+
+// Taking a `User` as a handler argument makes the route require a valid token. The user is
+// looked up on every request, along with the token version, so that a token stops working once
+// its user is deleted or changes their password. Ids are never reused, so the token of a
+// deleted user cannot pass for whoever registers next.
+impl FromRequest for User {
+    type Error = AppError;
+    type Future = Box<dyn Future<Item = Self, Error = Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let tokens = req
+            .get_app_data::<Tokens>()
+            .expect("Tokens must be registered as app data");
+        let (user_id, token_version) = match bearer_token(req).and_then(|t| tokens.verify(t)) {
+            Some(claims) => claims,
+            None => return Box::new(future::err(AppError::Unauthorized)),
+        };
+        let pool = req
+            .get_app_data::<Pool>()
+            .expect("Pool must be registered as app data");
+        Box::new(
+            web::block(move || {
+                let conn: &SqliteConnection = &pool.get().unwrap();
+                models::find_token_user(conn, user_id, token_version)
+            })
+            .map_err(|e| match AppError::from(e) {
+                AppError::RecordNotFound => AppError::Unauthorized,
+                e => e,
+            }),
+        )
+    }
+}
@@ -8,6 +8,22 @@ use std::fmt;
 pub enum AppError {
     RecordAlreadyExists,
     RecordNotFound,
+    // The request needs a valid session token and did not have one.
+    Unauthorized,
+    // The user is known but may not do what they asked.
+    Forbidden,
+    InvalidCredentials,
+    // A reply to a comment which is not on the same post, or no longer exists.
+    InvalidParent,
+    ThreadTooDeep,
+    // `after` names an item which no longer exists, so there is no position to continue from.
+    InvalidCursor,
+    // The post is not at a point in the review workflow where it can make the move asked for.
+    InvalidTransition,
+    // The change would leave nobody able to manage roles.
+    LastAdmin,
+    PasswordHashError(argon2::Error),
+    TemplateError(askama::Error),
     // Catch-all for other Diesel errors that aren't specifically handled.
     DatabaseError(diesel::result::Error),
     OperationCanceled,
@@ -18,6 +34,16 @@ impl fmt::Display for AppError {
         match self {
             AppError::RecordAlreadyExists => write!(f, "This record violates a unique constraint"),
             AppError::RecordNotFound => write!(f, "This record does not exist"),
+            AppError::Unauthorized => write!(f, "A valid session token is required"),
+            AppError::Forbidden => write!(f, "You are not allowed to do that"),
+            AppError::InvalidCredentials => write!(f, "Invalid username or password"),
+            AppError::InvalidParent => write!(f, "The parent comment is not on this post"),
+            AppError::ThreadTooDeep => write!(f, "Replies cannot be nested any deeper"),
+            AppError::InvalidCursor => write!(f, "The item to continue after does not exist"),
+            AppError::InvalidTransition => write!(f, "The post's status does not allow that"),
+            AppError::LastAdmin => write!(f, "The last admin cannot be demoted or deleted"),
+            AppError::PasswordHashError(e) => write!(f, "Password hashing failed: {}", e),
+            AppError::TemplateError(e) => write!(f, "Rendering the page failed: {}", e),
             AppError::DatabaseError(e) => write!(f, "Database error: {:?}", e),
             AppError::OperationCanceled => write!(f, "The running operation was canceled"),
         }
@@ -54,8 +80,14 @@ impl actix_web::ResponseError for AppError {
     fn error_response(&self) -> HttpResponse {
         let err = format!("{}", self);
         let mut builder = match self {
-            AppError::RecordAlreadyExists => HttpResponse::BadRequest(),
+            AppError::RecordAlreadyExists
+            | AppError::InvalidParent
+            | AppError::ThreadTooDeep
+            | AppError::InvalidCursor => HttpResponse::BadRequest(),
             AppError::RecordNotFound => HttpResponse::NotFound(),
+            AppError::Unauthorized | AppError::InvalidCredentials => HttpResponse::Unauthorized(),
+            AppError::Forbidden => HttpResponse::Forbidden(),
+            AppError::InvalidTransition | AppError::LastAdmin => HttpResponse::Conflict(),
             _ => HttpResponse::InternalServerError(),
         };
         builder.json(ErrorResponse { err })
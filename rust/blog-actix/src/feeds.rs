@@ -0,0 +1,161 @@
+use crate::errors::AppError;
+use crate::markdown;
+use crate::models::{Post, Tag, User};
+use crate::routes::urlencode;
+use askama::Template;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sha2::{Digest, Sha256};
+
+/// How many of the latest posts a feed carries.
+pub const FEED_LENGTH: i64 = 20;
+
+/// What the feeds say about the blog as a whole.
+///
+/// Feed readers fetch the feed from one place and show it somewhere else, so every link in it
+/// has to be absolute. `base_url` is where readers reach the site, such as
+/// `https://blog.example.com`, which need not be the address the server listens on.
+#[derive(Clone, Debug)]
+pub struct Site {
+    title: String,
+    base_url: String,
+}
+
+impl Site {
+    pub fn new(title: &str, base_url: &str) -> Self {
+        Site {
+            title: title.to_string(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+}
+
+// One post as it appears in a feed, with its Markdown already rendered.
+struct Entry {
+    title: String,
+    url: String,
+    author: String,
+    author_url: String,
+    published: DateTime<Utc>,
+    updated: DateTime<Utc>,
+    tags: Vec<String>,
+    content: String,
+}
+
+impl Entry {
+    fn new(site: &Site, post: Post, author: &User, tags: Vec<Tag>) -> Self {
+        Entry {
+            url: site.url(&format!("/post/{}", post.id)),
+            author: author.username.clone(),
+            author_url: author_url(site, author),
+            published: utc(post.created_at),
+            updated: utc(post.updated_at),
+            tags: tags.into_iter().map(|tag| tag.name).collect(),
+            content: markdown::to_html(&post.body),
+            title: post.title,
+        }
+    }
+}
+
+fn author_url(site: &Site, author: &User) -> String {
+    site.url(&format!("/author/{}", urlencode(&author.username)))
+}
+
+// The database keeps times in UTC without saying so.
+fn utc(date: NaiveDateTime) -> DateTime<Utc> {
+    DateTime::from_utc(date, Utc)
+}
+
+/// A feed ready to be written out as either Atom or RSS.
+pub struct Feed {
+    title: String,
+    // Where the feed itself lives, which Atom also uses as the feed's id.
+    self_url: String,
+    // The page the feed mirrors.
+    link: String,
+    updated: DateTime<Utc>,
+    entries: Vec<Entry>,
+}
+
+impl Feed {
+    /// The feed of every published post. `posts` are as `models::all_posts` returns them.
+    pub fn site<C>(site: &Site, path: &str, posts: Vec<((Post, User), C, Vec<Tag>)>) -> Self {
+        let entries = posts
+            .into_iter()
+            .map(|((post, author), _, tags)| Entry::new(site, post, &author, tags))
+            .collect();
+        Feed::new(site.title.clone(), site.url(path), site.url("/"), entries)
+    }
+
+    /// The feed of one author's published posts, as `models::user_posts` returns them.
+    pub fn author<C>(
+        site: &Site,
+        path: &str,
+        author: &User,
+        posts: Vec<(Post, C, Vec<Tag>)>,
+    ) -> Self {
+        let entries = posts
+            .into_iter()
+            .map(|(post, _, tags)| Entry::new(site, post, author, tags))
+            .collect();
+        Feed::new(
+            format!("{}: posts by {}", site.title, author.username),
+            site.url(path),
+            author_url(site, author),
+            entries,
+        )
+    }
+
+    fn new(title: String, self_url: String, link: String, mut entries: Vec<Entry>) -> Self {
+        // The posts are loaded with room for one more to tell whether there is a next page,
+        // which a feed has no use for.
+        entries.truncate(FEED_LENGTH as usize);
+        // A feed changes whenever any of its posts does. An empty feed has never changed.
+        let updated = entries
+            .iter()
+            .map(|entry| entry.updated)
+            .max()
+            .unwrap_or_else(|| utc(NaiveDateTime::from_timestamp(0, 0)));
+        Feed {
+            title,
+            self_url,
+            link,
+            updated,
+            entries,
+        }
+    }
+
+    pub fn to_atom(&self) -> Result<String, AppError> {
+        AtomFeed { feed: self }
+            .render()
+            .map_err(AppError::TemplateError)
+    }
+
+    pub fn to_rss(&self) -> Result<String, AppError> {
+        RssFeed { feed: self }
+            .render()
+            .map_err(AppError::TemplateError)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "atom.xml")]
+struct AtomFeed<'a> {
+    feed: &'a Feed,
+}
+
+#[derive(Template)]
+#[template(path = "rss.xml")]
+struct RssFeed<'a> {
+    feed: &'a Feed,
+}
+
+/// A strong `ETag` for a rendered feed. Rendering the same posts always gives the same bytes,
+/// so a hash of the body changes exactly when the feed does.
+pub fn etag(body: &str) -> String {
+    let digest = Sha256::digest(body.as_bytes());
+    format!("\"{}\"", hex::encode(&digest[..16]))
+}
@@ -7,10 +7,18 @@ use actix_web::{middleware, App, HttpServer};
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
 
+mod auth;
 mod errors;
+mod feeds;
+mod markdown;
 mod models;
+mod pagination;
+mod permissions;
 mod routes;
 mod schema;
+mod search;
+
+pub use feeds::Site;
 
 type Pool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
 
@@ -23,17 +31,20 @@ impl Blog {
         Blog { port }
     }
 
-    pub fn run(&self, database_url: String) -> std::io::Result<()> {
+    pub fn run(&self, database_url: String, secret_key: String, site: Site) -> std::io::Result<()> {
         let manager = ConnectionManager::<SqliteConnection>::new(database_url);
         // `Pool` is just an `Arc` wrapper around the struct that manages connections.
         let pool = r2d2::Pool::builder()
             .build(manager)
             .expect("Failed to create pool.");
+        let tokens = auth::Tokens::new(&secret_key)?;
 
         println!("Starting http server: 127.0.0.1:{}", self.port);
         HttpServer::new(move || {
             App::new()
                 .data(pool.clone())
+                .data(tokens.clone())
+                .data(site.clone())
                 .wrap(middleware::Logger::default())
                 // `configure` takes an argument that satisfies the `FnOnce(&mut ServiceConfig)`
                 // trait bound, meaning we guarantee it's okay to only call it once. We can thus
@@ -41,6 +52,12 @@ impl Blog {
                 .configure(routes::users::configure)
                 .configure(routes::posts::configure)
                 .configure(routes::comments::configure)
+                .configure(routes::reviews::configure)
+                .configure(routes::search::configure)
+                .configure(routes::tags::configure)
+                .configure(routes::feeds::configure)
+                // The HTML site goes last, as it serves `/` and the static files.
+                .configure(routes::site::configure)
         })
         .bind(("127.0.0.1", self.port))?
         .run()
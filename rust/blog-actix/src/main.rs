@@ -7,6 +7,12 @@ fn main() -> std::io::Result<()> {
     env::set_var("RUST LOG", "actix_web=info");
     env_logger::init();
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let app = blog_actix::Blog::new(8998);
-    app.run(database_url)
+    let secret_key = env::var("SECRET_KEY").expect("SECRET_KEY must be set");
+    let port = 8998;
+    // Feeds link back to the site, so they need the address readers know it by.
+    let site_title = env::var("SITE_TITLE").unwrap_or_else(|_| "blog-actix".to_string());
+    let base_url = env::var("BASE_URL").unwrap_or_else(|_| format!("http://127.0.0.1:{}", port));
+    let site = blog_actix::Site::new(&site_title, &base_url);
+    let app = blog_actix::Blog::new(port);
+    app.run(database_url, secret_key, site)
 }
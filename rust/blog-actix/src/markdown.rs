@@ -0,0 +1,16 @@
+use pulldown_cmark::{html, Options, Parser};
+
+/// Renders a post body written in Markdown to HTML which is safe to put into a page.
+///
+/// Markdown passes raw HTML straight through, so the output is cleaned with ammonia. That keeps
+/// formatting, images and links, but drops scripts, event handlers, styles and anything else
+/// which could run in a reader's browser.
+pub fn to_html(markdown: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(markdown, options));
+    ammonia::clean(&unsafe_html)
+}
@@ -1,15 +1,150 @@
 use crate::errors::AppError;
+use crate::pagination::{ListParams, Sort};
 use crate::schema::comments;
+use crate::schema::post_tags;
 use crate::schema::posts;
+use crate::schema::reviews;
+use crate::schema::tags;
 use crate::schema::users;
+use crate::search::strip_markers;
+use chrono::NaiveDateTime;
+use diesel::deserialize::{self, FromSql};
+use diesel::dsl::now;
+use diesel::dsl::sql;
 use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::{BigInt, Bool, Text};
+use diesel::sqlite::Sqlite;
+use std::collections::HashMap;
+use std::io::Write;
 
 type Result<T> = std::result::Result<T, AppError>;
 
-#[derive(Queryable, Identifiable, Serialize, Debug, PartialEq)]
+// Everything but the password hash, which is what every query that loads a `User` selects.
+type UserColumns = (
+    users::id,
+    users::username,
+    users::role,
+    users::created_at,
+    users::updated_at,
+);
+const USER_COLUMNS: UserColumns = (
+    users::id,
+    users::username,
+    users::role,
+    users::created_at,
+    users::updated_at,
+);
+
+#[derive(Queryable, Identifiable, Serialize, Debug, Clone, PartialEq)]
 pub struct User {
     pub id: i32,
     pub username: String,
+    pub role: Role,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+// What a user may do beyond managing their own posts and comments. See `permissions` for the
+// rules themselves.
+#[derive(AsExpression, FromSqlRow, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[sql_type = "Text"]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Author,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Author => "author",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for Role {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Sqlite> for Role {
+    fn from_sql(
+        bytes: Option<&<Sqlite as diesel::backend::Backend>::RawValue>,
+    ) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "author" => Ok(Role::Author),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!("Unknown role {}", other).into()),
+        }
+    }
+}
+
+/// Where a post is in the editorial workflow, the same one blog-oop models as types.
+///
+/// A draft is put up for review. A reviewer, who cannot be the author, approves it or sends it
+/// back to be a draft. An approved post is published by its author, and a published one can be
+/// archived, from where it has to go through review again to come back. Editing the text of a
+/// published post puts it back up for review.
+#[derive(AsExpression, FromSqlRow, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[sql_type = "Text"]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Draft,
+    PendingReview,
+    Approved,
+    Published,
+    Archived,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Draft => "draft",
+            Status::PendingReview => "pending_review",
+            Status::Approved => "approved",
+            Status::Published => "published",
+            Status::Archived => "archived",
+        }
+    }
+
+    pub fn can_become(self, next: Status) -> bool {
+        matches!(
+            (self, next),
+            (Status::Draft, Status::PendingReview)
+                | (Status::Archived, Status::PendingReview)
+                | (Status::PendingReview, Status::Approved)
+                | (Status::PendingReview, Status::Draft)
+                | (Status::Approved, Status::Published)
+                | (Status::Published, Status::Archived)
+        )
+    }
+}
+
+impl ToSql<Text, Sqlite> for Status {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Sqlite> for Status {
+    fn from_sql(
+        bytes: Option<&<Sqlite as diesel::backend::Backend>::RawValue>,
+    ) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "draft" => Ok(Status::Draft),
+            "pending_review" => Ok(Status::PendingReview),
+            "approved" => Ok(Status::Approved),
+            "published" => Ok(Status::Published),
+            "archived" => Ok(Status::Archived),
+            other => Err(format!("Unknown status {}", other).into()),
+        }
+    }
 }
 
 #[derive(Queryable, Associations, Identifiable, Serialize, Debug)]
@@ -19,7 +154,52 @@ pub struct Post {
     pub user_id: i32,
     pub title: String,
     pub body: String,
-    pub published: bool,
+    pub status: Status,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+// An approval or rejection of a post, with the reviewer's note if they left one.
+#[derive(Queryable, Identifiable, Associations, Serialize, Debug)]
+#[belongs_to(Post)]
+pub struct Review {
+    pub id: i32,
+    pub post_id: i32,
+    pub reviewer_id: i32,
+    pub approved: bool,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Identifiable, Serialize, Debug, Clone)]
+pub struct Tag {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Queryable, Identifiable, Associations, Debug)]
+#[belongs_to(Post)]
+#[belongs_to(Tag)]
+#[primary_key(post_id, tag_id)]
+pub struct PostTag {
+    pub post_id: i32,
+    pub tag_id: i32,
+}
+
+#[derive(Queryable, Serialize, Debug)]
+pub struct TagCount {
+    pub id: i32,
+    pub name: String,
+    // Published posts only, like every other public list.
+    pub posts: i64,
+}
+
+/// Stands in for the comments of each post in a list of posts, which would otherwise have no
+/// bound on their size. `url` lists the comments themselves.
+#[derive(Serialize, Debug)]
+pub struct CommentCount {
+    pub count: i64,
+    pub url: String,
 }
 
 #[derive(Queryable, Identifiable, Associations, Serialize, Debug)]
@@ -30,17 +210,70 @@ pub struct Comment {
     pub user_id: i32,
     pub post_id: i32,
     pub body: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub deleted_at: Option<NaiveDateTime>,
+    pub parent_id: Option<i32>,
+}
+
+/// How deeply replies may nest. A top level comment is at depth 0.
+pub const MAX_COMMENT_DEPTH: usize = 8;
+
+/// A comment in the tree of a post's discussion.
+///
+/// A deleted comment which has replies stays in the tree so that they keep their place, but
+/// without its author or body.
+#[derive(Serialize, Debug)]
+pub struct CommentNode {
+    pub id: i32,
+    pub post_id: i32,
+    pub parent_id: Option<i32>,
+    pub user: Option<User>,
+    pub body: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub deleted_at: Option<NaiveDateTime>,
+    pub replies: Vec<CommentNode>,
+    // The number of replies shown at any depth, which may be more than `replies` holds once the
+    // depth limit is reached.
+    pub reply_count: usize,
+}
+
+// Fields left as `None` are not changed. The `updated_at` column is set alongside them.
+#[derive(AsChangeset, Default)]
+#[table_name = "users"]
+pub struct UserChanges {
+    pub username: Option<String>,
+    pub password_hash: Option<String>,
 }
 
-pub fn create_user(conn: &SqliteConnection, username: &str) -> Result<User> {
+#[derive(AsChangeset, Deserialize, Debug)]
+#[table_name = "posts"]
+pub struct PostChanges {
+    pub title: Option<String>,
+    pub body: Option<String>,
+}
+
+#[derive(AsChangeset, Deserialize, Debug)]
+#[table_name = "comments"]
+pub struct CommentChanges {
+    pub body: Option<String>,
+}
+
+pub fn create_user(conn: &SqliteConnection, username: &str, password_hash: &str) -> Result<User> {
     conn.transaction(|| {
         diesel::insert_into(users::table)
-            .values(users::username.eq(username))
+            .values((
+                users::username.eq(username),
+                users::password_hash.eq(password_hash),
+                users::created_at.eq(now),
+                users::updated_at.eq(now),
+            ))
             .execute(conn)?;
 
         users::table
             .order(users::id.desc())
-            .select((users::id, users::username))
+            .select(USER_COLUMNS)
             .first(conn)
             // `map_err` transforms the error variant with the provided function, or
             // returns the success variant. We can pass `Into::into` to let Rust know
@@ -61,98 +294,536 @@ pub fn find_user<'a>(conn: &SqliteConnection, key: UserKey<'a>) -> Result<User>
     match key {
         UserKey::Username(name) => users::table
             .filter(users::username.eq(name))
-            .select((users::id, users::username))
+            .select(USER_COLUMNS)
             .first::<User>(conn)
             .map_err(AppError::from),
         UserKey::ID(id) => users::table
             .find(id)
-            .select((users::id, users::username))
+            .select(USER_COLUMNS)
             .first::<User>(conn)
             .map_err(Into::into),
     }
 }
 
-pub fn create_post(conn: &SqliteConnection, user: &User, title: &str, body: &str) -> Result<Post> {
+// The password hash is only ever loaded here, so that a `User` can be serialized anywhere
+// without leaking it. It comes with the token version a token issued now has to carry.
+pub fn find_credentials(conn: &SqliteConnection, username: &str) -> Result<(User, String, i32)> {
+    users::table
+        .filter(users::username.eq(username))
+        .select((USER_COLUMNS, users::password_hash, users::token_version))
+        .first::<(User, String, i32)>(conn)
+        .map_err(Into::into)
+}
+
+// The user a token was issued to, as long as nothing has revoked the token since.
+pub fn find_token_user(conn: &SqliteConnection, user_id: i32, token_version: i32) -> Result<User> {
+    users::table
+        .find(user_id)
+        .filter(users::token_version.eq(token_version))
+        .select(USER_COLUMNS)
+        .first::<User>(conn)
+        .map_err(Into::into)
+}
+
+pub fn create_post(
+    conn: &SqliteConnection,
+    user: &User,
+    title: &str,
+    body: &str,
+    tags: &[String],
+) -> Result<Post> {
     conn.transaction(|| {
         diesel::insert_into(posts::table)
             .values((
                 posts::user_id.eq(user.id),
-                posts::title.eq(title),
-                posts::body.eq(body),
+                posts::title.eq(strip_markers(title)),
+                posts::body.eq(strip_markers(body)),
+                posts::created_at.eq(now),
+                posts::updated_at.eq(now),
             ))
             .execute(conn)?;
 
-        posts::table
+        let post: Post = posts::table
             .order(posts::id.desc())
             .select(posts::all_columns)
-            .first(conn)
-            .map_err(Into::into)
+            .first(conn)?;
+        set_post_tags(conn, post.id, tags)?;
+        Ok(post)
+    })
+}
+
+// Tags are compared without regard to case or surrounding space, so they are stored that way.
+fn normalize_tags(names: &[String]) -> Vec<String> {
+    let mut names: Vec<String> = names
+        .iter()
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+// Replaces the tags of a post, creating any tag which does not exist yet. Tags which are left
+// without posts are kept, as a tag's id may already be known to clients.
+fn set_post_tags(conn: &SqliteConnection, post_id: i32, names: &[String]) -> Result<()> {
+    let names = normalize_tags(names);
+    diesel::delete(post_tags::table.filter(post_tags::post_id.eq(post_id))).execute(conn)?;
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    let new_tags: Vec<_> = names.iter().map(|name| tags::name.eq(name)).collect();
+    diesel::insert_or_ignore_into(tags::table)
+        .values(&new_tags)
+        .execute(conn)?;
+    let links: Vec<_> = tags::table
+        .filter(tags::name.eq_any(&names))
+        .select(tags::id)
+        .load::<i32>(conn)?
+        .into_iter()
+        .map(|tag_id| (post_tags::post_id.eq(post_id), post_tags::tag_id.eq(tag_id)))
+        .collect();
+    diesel::insert_into(post_tags::table)
+        .values(&links)
+        .execute(conn)?;
+    Ok(())
+}
+
+pub fn post_tags(conn: &SqliteConnection, post_id: i32) -> Result<Vec<Tag>> {
+    tags::table
+        .inner_join(post_tags::table)
+        .filter(post_tags::post_id.eq(post_id))
+        .order(tags::name)
+        .select(tags::all_columns)
+        .load::<Tag>(conn)
+        .map_err(Into::into)
+}
+
+// Diesel 1.x will not mix `count` with plain columns in a select, even with a `group_by`, so
+// the count is written as SQL.
+pub fn tag_counts(conn: &SqliteConnection) -> Result<Vec<TagCount>> {
+    tags::table
+        .inner_join(post_tags::table.inner_join(posts::table))
+        .filter(posts::status.eq(Status::Published))
+        .group_by(tags::id)
+        .select((tags::id, tags::name, sql::<BigInt>("COUNT(*)")))
+        .order(tags::name)
+        .load::<TagCount>(conn)
+        .map_err(Into::into)
+}
+
+pub fn set_role(conn: &SqliteConnection, user_id: i32, role: Role) -> Result<User> {
+    conn.transaction(|| {
+        diesel::update(users::table.find(user_id))
+            .set(users::role.eq(role))
+            .execute(conn)?;
+
+        find_user(conn, UserKey::ID(user_id))
     })
 }
 
-pub fn publish_post(conn: &SqliteConnection, post_id: i32) -> Result<Post> {
+// A new password revokes every token issued with the old one.
+pub fn update_user(conn: &SqliteConnection, user_id: i32, changes: &UserChanges) -> Result<User> {
     conn.transaction(|| {
-        diesel::update(posts::table.filter(posts::id.eq(post_id)))
-            .set(posts::published.eq(true))
+        diesel::update(users::table.find(user_id))
+            .set((changes, users::updated_at.eq(now)))
             .execute(conn)?;
+        if changes.password_hash.is_some() {
+            diesel::update(users::table.find(user_id))
+                .set(users::token_version.eq(users::token_version + 1))
+                .execute(conn)?;
+        }
 
-        posts::table
-            .find(post_id)
-            .select(posts::all_columns)
-            .first(conn)
-            .map_err(Into::into)
+        find_user(conn, UserKey::ID(user_id))
     })
 }
 
-pub fn all_posts(conn: &SqliteConnection) -> Result<Vec<((Post, User), Vec<(Comment, User)>)>> {
-    let query = posts::table
-        .order(posts::id.desc())
-        .filter(posts::published.eq(true))
-        .inner_join(users::table)
-        .select((posts::all_columns, (users::id, users::username)));
-    let posts_with_user = query.load::<(Post, User)>(conn)?;
-    let (posts, post_users): (Vec<_>, Vec<_>) = posts_with_user.into_iter().unzip();
+pub fn count_admins(conn: &SqliteConnection) -> Result<i64> {
+    users::table
+        .filter(users::role.eq(Role::Admin))
+        .count()
+        .get_result(conn)
+        .map_err(Into::into)
+}
+
+// Deleting a user takes their posts with them, along with the comments others left on those
+// posts and their reviews. Their comments elsewhere are deleted the way any comment is, so
+// that the replies to them stay in their threads. The reviews they wrote of other posts go too,
+// as a review is only ever shown with its reviewer.
+pub fn delete_user(conn: &SqliteConnection, user_id: i32) -> Result<User> {
+    conn.transaction(|| {
+        let user = find_user(conn, UserKey::ID(user_id))?;
+        let post_ids = posts::table
+            .filter(posts::user_id.eq(user_id))
+            .select(posts::id);
+        diesel::delete(comments::table.filter(comments::post_id.eq_any(post_ids))).execute(conn)?;
+        let post_ids = posts::table
+            .filter(posts::user_id.eq(user_id))
+            .select(posts::id);
+        diesel::delete(post_tags::table.filter(post_tags::post_id.eq_any(post_ids)))
+            .execute(conn)?;
+        let post_ids = posts::table
+            .filter(posts::user_id.eq(user_id))
+            .select(posts::id);
+        diesel::delete(reviews::table.filter(reviews::post_id.eq_any(post_ids))).execute(conn)?;
+        diesel::delete(reviews::table.filter(reviews::reviewer_id.eq(user_id))).execute(conn)?;
+        diesel::update(
+            comments::table
+                .filter(comments::user_id.eq(user_id))
+                .filter(comments::deleted_at.is_null()),
+        )
+        .set(comments::deleted_at.eq(now))
+        .execute(conn)?;
+        diesel::delete(posts::table.filter(posts::user_id.eq(user_id))).execute(conn)?;
+        diesel::delete(users::table.find(user_id)).execute(conn)?;
+        Ok(user)
+    })
+}
+
+pub fn find_post(conn: &SqliteConnection, post_id: i32) -> Result<Post> {
+    posts::table
+        .find(post_id)
+        .select(posts::all_columns)
+        .first(conn)
+        .map_err(Into::into)
+}
+
+// Tags are only replaced when they are given. Changing the text of a post which is under review
+// or approved sends it back to being a draft, as what was reviewed is no longer what would be
+// published. Changing the text of a published post takes it down until the new text has been
+// reviewed.
+pub fn update_post(
+    conn: &SqliteConnection,
+    post_id: i32,
+    changes: &PostChanges,
+    tags: Option<&[String]>,
+) -> Result<Post> {
+    let changes = PostChanges {
+        title: changes.title.as_deref().map(strip_markers),
+        body: changes.body.as_deref().map(strip_markers),
+    };
+    conn.transaction(|| {
+        let post = find_post(conn, post_id)?;
+        diesel::update(posts::table.find(post_id))
+            .set((&changes, posts::updated_at.eq(now)))
+            .execute(conn)?;
+        let edited = changes.title.is_some() || changes.body.is_some();
+        let status = match post.status {
+            Status::PendingReview | Status::Approved if edited => Some(Status::Draft),
+            Status::Published if edited => Some(Status::PendingReview),
+            _ => None,
+        };
+        if let Some(status) = status {
+            diesel::update(posts::table.find(post_id))
+                .set(posts::status.eq(status))
+                .execute(conn)?;
+        }
+        if let Some(tags) = tags {
+            set_post_tags(conn, post_id, tags)?;
+        }
+
+        find_post(conn, post_id)
+    })
+}
+
+// The comments on a post go with it, whether or not they were already soft deleted.
+pub fn delete_post(conn: &SqliteConnection, post_id: i32) -> Result<Post> {
+    conn.transaction(|| {
+        let post = find_post(conn, post_id)?;
+        diesel::delete(comments::table.filter(comments::post_id.eq(post_id))).execute(conn)?;
+        diesel::delete(post_tags::table.filter(post_tags::post_id.eq(post_id))).execute(conn)?;
+        diesel::delete(reviews::table.filter(reviews::post_id.eq(post_id))).execute(conn)?;
+        diesel::delete(posts::table.find(post_id)).execute(conn)?;
+        Ok(post)
+    })
+}
+
+// The status is checked inside the transaction, so that two requests cannot both move a post
+// on from the same status.
+pub fn set_status(conn: &SqliteConnection, post_id: i32, status: Status) -> Result<Post> {
+    conn.transaction(|| {
+        let post = find_post(conn, post_id)?;
+        if !post.status.can_become(status) {
+            return Err(AppError::InvalidTransition);
+        }
+        diesel::update(posts::table.find(post_id))
+            .set((posts::status.eq(status), posts::updated_at.eq(now)))
+            .execute(conn)?;
+
+        find_post(conn, post_id)
+    })
+}
+
+// Approving moves a post on, rejecting sends it back to be a draft. Either way the review is
+// kept so the author can see what was said.
+pub fn review_post(
+    conn: &SqliteConnection,
+    post_id: i32,
+    reviewer_id: i32,
+    approved: bool,
+    note: Option<&str>,
+) -> Result<Post> {
+    conn.transaction(|| {
+        let status = if approved {
+            Status::Approved
+        } else {
+            Status::Draft
+        };
+        let post = set_status(conn, post_id, status)?;
+        diesel::insert_into(reviews::table)
+            .values((
+                reviews::post_id.eq(post_id),
+                reviews::reviewer_id.eq(reviewer_id),
+                reviews::approved.eq(approved),
+                reviews::note.eq(note),
+                reviews::created_at.eq(now),
+            ))
+            .execute(conn)?;
+        Ok(post)
+    })
+}
 
-    let comments = Comment::belonging_to(&posts)
+pub fn post_reviews(conn: &SqliteConnection, post_id: i32) -> Result<Vec<(Review, User)>> {
+    reviews::table
+        .filter(reviews::post_id.eq(post_id))
         .inner_join(users::table)
-        .select((comments::all_columns, (users::id, users::username)))
-        .load::<(Comment, User)>(conn)?
+        .order(reviews::id.asc())
+        .select((reviews::all_columns, USER_COLUMNS))
+        .load::<(Review, User)>(conn)
+        .map_err(Into::into)
+}
+
+// A post in a list, together with how many of its comments have not been deleted, and its tags.
+type PostListing = (Post, CommentCount, Vec<Tag>);
+pub type PostWithAuthor = ((Post, User), CommentCount, Vec<Tag>);
+
+// The filters, sort order and cursor of a list of posts. Rows are ordered with the id as a
+// tie-breaker so that `after` always picks up exactly where the last page stopped.
+fn posts_page(
+    conn: &SqliteConnection,
+    params: &ListParams,
+    sort: Sort,
+) -> Result<posts::BoxedQuery<'static, Sqlite>> {
+    let mut query = posts::table.into_boxed();
+    if let Some(author) = params.author {
+        query = query.filter(posts::user_id.eq(author));
+    }
+    if let Some(status) = params.status {
+        query = query.filter(posts::status.eq(status));
+    }
+    if let Some(tag) = &params.tag {
+        let tagged = post_tags::table
+            .inner_join(tags::table)
+            .filter(tags::name.eq(tag.trim().to_lowercase()))
+            .select(post_tags::post_id);
+        query = query.filter(posts::id.eq_any(tagged));
+    }
+    if let Some(since) = params.since {
+        query = query.filter(posts::created_at.ge(since));
+    }
+    if let Some(until) = params.until {
+        query = query.filter(posts::created_at.lt(until));
+    }
+    query = match sort {
+        Sort::Newest => {
+            if let Some(after) = params.after {
+                query = query.filter(posts::id.lt(after));
+            }
+            query.order(posts::id.desc())
+        }
+        Sort::Oldest => {
+            if let Some(after) = params.after {
+                query = query.filter(posts::id.gt(after));
+            }
+            query.order(posts::id.asc())
+        }
+        Sort::Updated => {
+            if let Some(after) = params.after {
+                let cursor: NaiveDateTime = posts::table
+                    .find(after)
+                    .select(posts::updated_at)
+                    .first(conn)
+                    .optional()?
+                    .ok_or(AppError::InvalidCursor)?;
+                query = query.filter(
+                    posts::updated_at
+                        .lt(cursor)
+                        .or(posts::updated_at.eq(cursor).and(posts::id.lt(after))),
+                );
+            }
+            query.order((posts::updated_at.desc(), posts::id.desc()))
+        }
+    };
+    Ok(query.limit(params.fetch_limit()))
+}
+
+fn comments_page(
+    conn: &SqliteConnection,
+    params: &ListParams,
+    sort: Sort,
+) -> Result<comments::BoxedQuery<'static, Sqlite>> {
+    let mut query = comments::table.into_boxed();
+    if let Some(author) = params.author {
+        query = query.filter(comments::user_id.eq(author));
+    }
+    if let Some(since) = params.since {
+        query = query.filter(comments::created_at.ge(since));
+    }
+    if let Some(until) = params.until {
+        query = query.filter(comments::created_at.lt(until));
+    }
+    query = match sort {
+        Sort::Newest => {
+            if let Some(after) = params.after {
+                query = query.filter(comments::id.lt(after));
+            }
+            query.order(comments::id.desc())
+        }
+        Sort::Oldest => {
+            if let Some(after) = params.after {
+                query = query.filter(comments::id.gt(after));
+            }
+            query.order(comments::id.asc())
+        }
+        Sort::Updated => {
+            if let Some(after) = params.after {
+                let cursor: NaiveDateTime = comments::table
+                    .find(after)
+                    .select(comments::updated_at)
+                    .first(conn)
+                    .optional()?
+                    .ok_or(AppError::InvalidCursor)?;
+                query = query.filter(
+                    comments::updated_at
+                        .lt(cursor)
+                        .or(comments::updated_at.eq(cursor).and(comments::id.lt(after))),
+                );
+            }
+            query.order((comments::updated_at.desc(), comments::id.desc()))
+        }
+    };
+    Ok(query.limit(params.fetch_limit()))
+}
+
+// Looks up the users with the given ids, so that a page can be paired with its authors
+// without joining inside the boxed query.
+fn users_by_id(conn: &SqliteConnection, ids: Vec<i32>) -> Result<HashMap<i32, User>> {
+    let users = users::table
+        .filter(users::id.eq_any(ids))
+        .select(USER_COLUMNS)
+        .load::<User>(conn)?;
+    Ok(users.into_iter().map(|u| (u.id, u)).collect())
+}
+
+// As with `tag_counts`, the count is written as SQL.
+fn with_comment_counts(conn: &SqliteConnection, posts: Vec<Post>) -> Result<Vec<PostListing>> {
+    let counts: HashMap<i32, i64> = comments::table
+        .filter(comments::post_id.eq_any(posts.iter().map(|p| p.id).collect::<Vec<_>>()))
+        .filter(comments::deleted_at.is_null())
+        .group_by(comments::post_id)
+        .select((comments::post_id, sql::<BigInt>("COUNT(*)")))
+        .load::<(i32, i64)>(conn)?
+        .into_iter()
+        .collect();
+    let tags = PostTag::belonging_to(&posts)
+        .inner_join(tags::table)
+        .order(tags::name)
+        .select((post_tags::all_columns, tags::all_columns))
+        .load::<(PostTag, Tag)>(conn)?
         .grouped_by(&posts);
 
-    Ok(posts.into_iter().zip(post_users).zip(comments).collect())
+    Ok(posts
+        .into_iter()
+        .zip(tags)
+        .map(|(post, tags)| {
+            let comments = CommentCount {
+                count: counts.get(&post.id).copied().unwrap_or(0),
+                url: format!("/posts/{}/comments", post.id),
+            };
+            let tags = tags.into_iter().map(|(_, tag)| tag).collect();
+            (post, comments, tags)
+        })
+        .collect())
+}
+
+// The public feed only ever shows published posts, whatever `status` asks for.
+pub fn all_posts(conn: &SqliteConnection, params: &ListParams) -> Result<Vec<PostWithAuthor>> {
+    posts_with_status(conn, params, Status::Published, Sort::Newest)
+}
+
+// The posts waiting for a reviewer, those which have waited longest first.
+pub fn review_queue(conn: &SqliteConnection, params: &ListParams) -> Result<Vec<PostWithAuthor>> {
+    posts_with_status(conn, params, Status::PendingReview, Sort::Oldest)
+}
+
+fn posts_with_status(
+    conn: &SqliteConnection,
+    params: &ListParams,
+    status: Status,
+    sort: Sort,
+) -> Result<Vec<PostWithAuthor>> {
+    let params = ListParams {
+        status: Some(status),
+        ..params.clone()
+    };
+    let posts = posts_page(conn, &params, params.sort.unwrap_or(sort))?.load::<Post>(conn)?;
+    let users = users_by_id(conn, posts.iter().map(|p| p.user_id).collect())?;
+
+    with_comment_counts(conn, posts)?
+        .into_iter()
+        .map(|(post, comments, tags)| {
+            let user = users
+                .get(&post.user_id)
+                .cloned()
+                .ok_or(AppError::RecordNotFound)?;
+            Ok(((post, user), comments, tags))
+        })
+        .collect()
 }
 
 pub fn user_posts(
     conn: &SqliteConnection,
     user_id: i32,
-) -> Result<Vec<(Post, Vec<(Comment, User)>)>> {
-    let posts = posts::table
-        .filter(posts::user_id.eq(user_id))
-        .order(posts::id.desc())
-        .select(posts::all_columns)
-        .load::<Post>(conn)?;
-
-    let comments = Comment::belonging_to(&posts)
-        .inner_join(users::table)
-        .select((comments::all_columns, (users::id, users::username)))
-        .load::<(Comment, User)>(conn)?
-        .grouped_by(&posts);
+    params: &ListParams,
+) -> Result<Vec<PostListing>> {
+    let params = ListParams {
+        author: Some(user_id),
+        ..params.clone()
+    };
+    let posts =
+        posts_page(conn, &params, params.sort.unwrap_or(Sort::Newest))?.load::<Post>(conn)?;
 
-    Ok(posts.into_iter().zip(comments).collect())
+    with_comment_counts(conn, posts)
 }
 
 pub fn create_comment(
     conn: &SqliteConnection,
     user_id: i32,
     post_id: i32,
+    parent_id: Option<i32>,
     body: &str,
 ) -> Result<Comment> {
     conn.transaction(|| {
+        if let Some(parent_id) = parent_id {
+            let parent = match find_comment(conn, parent_id) {
+                Ok(parent) if parent.post_id == post_id => parent,
+                Ok(_) | Err(AppError::RecordNotFound) => return Err(AppError::InvalidParent),
+                Err(e) => return Err(e),
+            };
+            if comment_depth(conn, &parent)? >= MAX_COMMENT_DEPTH {
+                return Err(AppError::ThreadTooDeep);
+            }
+        }
+
         diesel::insert_into(comments::table)
             .values((
                 comments::user_id.eq(user_id),
                 comments::post_id.eq(post_id),
-                comments::body.eq(body),
+                comments::parent_id.eq(parent_id),
+                comments::body.eq(strip_markers(body)),
+                comments::created_at.eq(now),
+                comments::updated_at.eq(now),
             ))
             .execute(conn)?;
 
@@ -164,33 +835,201 @@ pub fn create_comment(
     })
 }
 
-pub fn post_comments(conn: &SqliteConnection, post_id: i32) -> Result<Vec<(Comment, User)>> {
+// Walks up the parents, which never takes more than `MAX_COMMENT_DEPTH` queries.
+fn comment_depth(conn: &SqliteConnection, comment: &Comment) -> Result<usize> {
+    let mut depth = 0;
+    let mut parent_id = comment.parent_id;
+    while let Some(id) = parent_id {
+        depth += 1;
+        parent_id = comments::table
+            .find(id)
+            .select(comments::parent_id)
+            .first(conn)?;
+    }
+    Ok(depth)
+}
+
+// A comment which has been deleted is not found, as far as the API is concerned.
+pub fn find_comment(conn: &SqliteConnection, comment_id: i32) -> Result<Comment> {
     comments::table
-        .filter(comments::post_id.eq(post_id))
-        .inner_join(users::table)
-        .select((comments::all_columns, (users::id, users::username)))
-        .load::<(Comment, User)>(conn)
+        .find(comment_id)
+        .filter(comments::deleted_at.is_null())
+        .select(comments::all_columns)
+        .first(conn)
         .map_err(Into::into)
 }
 
-#[derive(Queryable, Serialize, Debug)]
+pub fn update_comment(
+    conn: &SqliteConnection,
+    comment_id: i32,
+    changes: &CommentChanges,
+) -> Result<Comment> {
+    let changes = CommentChanges {
+        body: changes.body.as_deref().map(strip_markers),
+    };
+    conn.transaction(|| {
+        diesel::update(comments::table.find(comment_id))
+            .set((&changes, comments::updated_at.eq(now)))
+            .execute(conn)?;
+
+        find_comment(conn, comment_id)
+    })
+}
+
+pub fn delete_comment(conn: &SqliteConnection, comment_id: i32) -> Result<Comment> {
+    conn.transaction(|| {
+        diesel::update(comments::table.find(comment_id))
+            .set(comments::deleted_at.eq(now))
+            .execute(conn)?;
+
+        comments::table
+            .find(comment_id)
+            .select(comments::all_columns)
+            .first(conn)
+            .map_err(Into::into)
+    })
+}
+
+// Whether any reply in the thread below `comments.id` has not been deleted.
+const LIVE_REPLY: &str = "EXISTS (
+    WITH RECURSIVE thread(id, deleted_at) AS (
+        SELECT replies.id, replies.deleted_at FROM comments AS replies
+        WHERE replies.parent_id = comments.id
+        UNION ALL
+        SELECT replies.id, replies.deleted_at FROM comments AS replies
+        JOIN thread ON replies.parent_id = thread.id
+    )
+    SELECT 1 FROM thread WHERE thread.deleted_at IS NULL
+)";
+
+// Pages through the top level comments, oldest first unless asked otherwise since they read
+// as a conversation, and hangs the replies to each under it down to `depth`. Replies are always
+// in the order they were written. A deleted top level comment is kept if any reply below it is
+// still there, which SQL works out by walking its thread, so that pages stay the size they were
+// asked to be.
+pub fn post_comments(
+    conn: &SqliteConnection,
+    post_id: i32,
+    params: &ListParams,
+) -> Result<Vec<CommentNode>> {
+    let roots = comments_page(conn, params, params.sort.unwrap_or(Sort::Oldest))?
+        .filter(comments::post_id.eq(post_id))
+        .filter(comments::parent_id.is_null())
+        .filter(comments::deleted_at.is_null().or(sql::<Bool>(LIVE_REPLY)))
+        .load::<Comment>(conn)?;
+    // Only the threads under this page are loaded, a level at a time, which takes no more than
+    // `MAX_COMMENT_DEPTH` queries.
+    let mut replies = Vec::new();
+    let mut parent_ids: Vec<i32> = roots.iter().map(|c| c.id).collect();
+    while !parent_ids.is_empty() {
+        let level = comments::table
+            .filter(comments::parent_id.eq_any(parent_ids))
+            .order(comments::id.asc())
+            .select(comments::all_columns)
+            .load::<Comment>(conn)?;
+        parent_ids = level.iter().map(|c| c.id).collect();
+        replies.extend(level);
+    }
+
+    let user_ids = roots
+        .iter()
+        .chain(replies.iter())
+        .filter(|c| c.deleted_at.is_none())
+        .map(|c| c.user_id)
+        .collect();
+    let users = users_by_id(conn, user_ids)?;
+    let mut children: HashMap<i32, Vec<Comment>> = HashMap::new();
+    for reply in replies {
+        children
+            .entry(reply.parent_id.unwrap_or_default())
+            .or_default()
+            .push(reply);
+    }
+
+    let depth = params.depth().min(MAX_COMMENT_DEPTH);
+    Ok(roots
+        .into_iter()
+        .map(|root| comment_node(root, &mut children, &users, depth))
+        .collect())
+}
+
+// Builds the node for a comment and its replies. A deleted reply is left out unless something
+// below it is still there. Replies below the depth limit are counted but not included.
+fn comment_node(
+    comment: Comment,
+    children: &mut HashMap<i32, Vec<Comment>>,
+    users: &HashMap<i32, User>,
+    depth: usize,
+) -> CommentNode {
+    let replies: Vec<CommentNode> = children
+        .remove(&comment.id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|reply| comment_node(reply, children, users, depth.saturating_sub(1)))
+        .filter(|reply| reply.deleted_at.is_none() || reply.reply_count > 0)
+        .collect();
+    let reply_count = replies.iter().map(|r| 1 + r.reply_count).sum();
+    let deleted = comment.deleted_at.is_some();
+    CommentNode {
+        id: comment.id,
+        post_id: comment.post_id,
+        parent_id: comment.parent_id,
+        user: if deleted {
+            None
+        } else {
+            users.get(&comment.user_id).cloned()
+        },
+        body: if deleted { None } else { Some(comment.body) },
+        created_at: comment.created_at,
+        updated_at: comment.updated_at,
+        deleted_at: comment.deleted_at,
+        replies: if depth == 0 { Vec::new() } else { replies },
+        reply_count,
+    }
+}
+
+#[derive(Queryable, Serialize, Debug, Clone)]
 pub struct PostWithComment {
     pub id: i32,
     pub title: String,
-    pub published: bool,
+    pub status: Status,
 }
 
+// Only the comments on published posts, so that the list gives nothing away about a post that
+// is not out yet.
 pub fn user_comments(
     conn: &SqliteConnection,
     user_id: i32,
+    params: &ListParams,
 ) -> Result<Vec<(Comment, PostWithComment)>> {
-    comments::table
-        .filter(comments::user_id.eq(user_id))
-        .inner_join(posts::table)
-        .select((
-            comments::all_columns,
-            (posts::id, posts::title, posts::published),
-        ))
-        .load::<(Comment, PostWithComment)>(conn)
-        .map_err(Into::into)
+    let params = ListParams {
+        author: Some(user_id),
+        ..params.clone()
+    };
+    let published = posts::table
+        .filter(posts::status.eq(Status::Published))
+        .select(posts::id);
+    let comments = comments_page(conn, &params, params.sort.unwrap_or(Sort::Newest))?
+        .filter(comments::deleted_at.is_null())
+        .filter(comments::post_id.eq_any(published))
+        .load::<Comment>(conn)?;
+    let post_ids: Vec<i32> = comments.iter().map(|c| c.post_id).collect();
+    let posts: HashMap<i32, PostWithComment> = posts::table
+        .filter(posts::id.eq_any(post_ids))
+        .select((posts::id, posts::title, posts::status))
+        .load::<PostWithComment>(conn)?
+        .into_iter()
+        .map(|p| (p.id, p))
+        .collect();
+
+    comments
+        .into_iter()
+        .map(|comment| {
+            let post = posts
+                .get(&comment.post_id)
+                .cloned()
+                .ok_or(AppError::RecordNotFound)?;
+            Ok((comment, post))
+        })
+        .collect()
 }
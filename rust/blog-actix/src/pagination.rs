@@ -0,0 +1,120 @@
+use crate::models::Status;
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Deserializer};
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    Newest,
+    Oldest,
+    // Most recently edited first.
+    Updated,
+}
+
+/// The query string accepted by the list endpoints.
+///
+/// `after` is the id of the last item of the previous page, which keeps a page stable while
+/// new posts and comments arrive, unlike an offset. With `sort=updated` that item has to still
+/// exist, as the page continues from when it was last edited. `since` and `until` take a date
+/// or a date and time and bound `created_at`, `since` inclusively and `until` exclusively.
+/// `tag` limits posts to those with that tag, and `depth` how far down a comment thread goes.
+/// A filter which does not make sense for an endpoint, such as `author` on
+/// `/users/{id}/posts`, is ignored, and so is `status` for anyone who may only see published
+/// posts.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct ListParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<Sort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth: Option<usize>,
+    #[serde(
+        default,
+        deserialize_with = "date_or_time",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub since: Option<NaiveDateTime>,
+    #[serde(
+        default,
+        deserialize_with = "date_or_time",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub until: Option<NaiveDateTime>,
+}
+
+/// The number of items to show for a requested `limit`, which is kept within bounds.
+pub fn clamp_limit(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+impl ListParams {
+    pub fn limit(&self) -> i64 {
+        clamp_limit(self.limit)
+    }
+
+    // How many levels of replies to include under each comment. Each endpoint has its own limit.
+    pub fn depth(&self) -> usize {
+        self.depth.unwrap_or(usize::MAX)
+    }
+
+    // One more row than is shown tells us whether there is a next page without a count.
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit() + 1
+    }
+}
+
+fn date_or_time<'de, D>(deserializer: D) -> Result<Option<NaiveDateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    NaiveDateTime::parse_from_str(&s, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S%.f"))
+        .or_else(|_| NaiveDate::parse_from_str(&s, "%Y-%m-%d").map(|d| d.and_hms(0, 0, 0)))
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("invalid date {}", s)))
+}
+
+/// One page of a list, with a link to the next one if there is more.
+#[derive(Serialize, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Builds a page from rows loaded with `fetch_limit`. The link repeats the request's own
+    /// query string with `after` moved on to the last item shown.
+    pub fn new<F>(mut items: Vec<T>, params: &ListParams, path: &str, id: F) -> Self
+    where
+        F: Fn(&T) -> i32,
+    {
+        let limit = params.limit() as usize;
+        let next = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(|last| {
+                let params = ListParams {
+                    after: Some(id(last)),
+                    ..params.clone()
+                };
+                let query = serde_urlencoded::to_string(&params).unwrap_or_default();
+                format!("{}?{}", path, query)
+            })
+        } else {
+            None
+        };
+        Page { items, next }
+    }
+}
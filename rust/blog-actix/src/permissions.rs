@@ -0,0 +1,225 @@
+use crate::errors::AppError;
+use crate::models::{
+    self, Comment, CommentChanges, CommentNode, Post, PostChanges, Review, Role, Status, User,
+    UserChanges, UserKey,
+};
+use crate::pagination::ListParams;
+use diesel::prelude::*;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+// The routes that change something on behalf of a user go through here rather than straight
+// to `models`. Each function loads what the rule needs, checks it, and only then does the
+// work, so `models` can stay free of any notion of who is asking. The check and the work share
+// a transaction, so what was checked still holds when the work is done.
+
+fn allow(allowed: bool) -> Result<()> {
+    if allowed {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden)
+    }
+}
+
+// Putting a post up for review, publishing, archiving, editing and deleting it all fall under
+// this rule.
+pub fn can_manage_post(user: &User, post: &Post) -> bool {
+    user.id == post.user_id || user.role == Role::Admin
+}
+
+// The same rule as `can_manage_post`, for all of an author's posts at once, e.g. to list their
+// drafts.
+pub fn can_manage_posts_of(user: &User, user_id: i32) -> bool {
+    user.id == user_id || user.role == Role::Admin
+}
+
+pub fn is_reviewer(user: &User) -> bool {
+    user.role == Role::Moderator || user.role == Role::Admin
+}
+
+// Nobody reviews their own post, not even an admin.
+pub fn can_review(user: &User, post: &Post) -> bool {
+    is_reviewer(user) && user.id != post.user_id
+}
+
+// Anyone may read a published post and its comments. Until then the post is only there for
+// those who can manage it.
+pub fn can_see_post(user: Option<&User>, post: &Post) -> bool {
+    post.status == Status::Published || user.iter().any(|user| can_manage_post(user, post))
+}
+
+pub fn can_manage_user(user: &User, user_id: i32) -> bool {
+    user.id == user_id || user.role == Role::Admin
+}
+
+pub fn can_edit_comment(user: &User, comment: &Comment) -> bool {
+    user.id == comment.user_id || user.role == Role::Admin
+}
+
+pub fn can_delete_comment(user: &User, comment: &Comment, post: &Post) -> bool {
+    user.id == comment.user_id
+        || user.id == post.user_id
+        || user.role == Role::Moderator
+        || user.role == Role::Admin
+}
+
+pub fn update_user(
+    conn: &SqliteConnection,
+    user: &User,
+    user_id: i32,
+    changes: &UserChanges,
+) -> Result<User> {
+    allow(can_manage_user(user, user_id))?;
+    models::update_user(conn, user_id, changes)
+}
+
+pub fn delete_user(conn: &SqliteConnection, user: &User, user_id: i32) -> Result<User> {
+    allow(can_manage_user(user, user_id))?;
+    conn.transaction(|| {
+        keep_an_admin(conn, user_id)?;
+        models::delete_user(conn, user_id)
+    })
+}
+
+// Moves a post along the workflow on behalf of its author: into review, out to the public, or
+// into the archive. Reviews go through `review_post`.
+pub fn set_status(
+    conn: &SqliteConnection,
+    user: &User,
+    post_id: i32,
+    status: Status,
+) -> Result<Post> {
+    conn.transaction(|| {
+        let post = models::find_post(conn, post_id)?;
+        allow(can_manage_post(user, &post))?;
+        models::set_status(conn, post_id, status)
+    })
+}
+
+pub fn review_post(
+    conn: &SqliteConnection,
+    user: &User,
+    post_id: i32,
+    approved: bool,
+    note: Option<&str>,
+) -> Result<Post> {
+    conn.transaction(|| {
+        let post = models::find_post(conn, post_id)?;
+        allow(can_review(user, &post))?;
+        models::review_post(conn, post_id, user.id, approved, note)
+    })
+}
+
+pub fn post_reviews(
+    conn: &SqliteConnection,
+    user: &User,
+    post_id: i32,
+) -> Result<Vec<(Review, User)>> {
+    let post = models::find_post(conn, post_id)?;
+    allow(can_manage_post(user, &post) || is_reviewer(user))?;
+    models::post_reviews(conn, post_id)
+}
+
+pub fn review_queue(
+    conn: &SqliteConnection,
+    user: &User,
+    params: &ListParams,
+) -> Result<Vec<models::PostWithAuthor>> {
+    allow(is_reviewer(user))?;
+    models::review_queue(conn, params)
+}
+
+pub fn update_post(
+    conn: &SqliteConnection,
+    user: &User,
+    post_id: i32,
+    changes: &PostChanges,
+    tags: Option<&[String]>,
+) -> Result<Post> {
+    conn.transaction(|| {
+        let post = models::find_post(conn, post_id)?;
+        allow(can_manage_post(user, &post))?;
+        models::update_post(conn, post_id, changes, tags)
+    })
+}
+
+pub fn delete_post(conn: &SqliteConnection, user: &User, post_id: i32) -> Result<Post> {
+    conn.transaction(|| {
+        let post = models::find_post(conn, post_id)?;
+        allow(can_manage_post(user, &post))?;
+        models::delete_post(conn, post_id)
+    })
+}
+
+// A post the caller cannot see is as good as missing, so it is not found rather than forbidden.
+pub fn find_post(conn: &SqliteConnection, user: Option<&User>, post_id: i32) -> Result<Post> {
+    let post = models::find_post(conn, post_id)?;
+    if !can_see_post(user, &post) {
+        return Err(AppError::RecordNotFound);
+    }
+    Ok(post)
+}
+
+pub fn create_comment(
+    conn: &SqliteConnection,
+    user: &User,
+    post_id: i32,
+    parent_id: Option<i32>,
+    body: &str,
+) -> Result<Comment> {
+    conn.transaction(|| {
+        find_post(conn, Some(user), post_id)?;
+        models::create_comment(conn, user.id, post_id, parent_id, body)
+    })
+}
+
+pub fn post_comments(
+    conn: &SqliteConnection,
+    user: Option<&User>,
+    post_id: i32,
+    params: &ListParams,
+) -> Result<Vec<CommentNode>> {
+    find_post(conn, user, post_id)?;
+    models::post_comments(conn, post_id, params)
+}
+
+pub fn update_comment(
+    conn: &SqliteConnection,
+    user: &User,
+    comment_id: i32,
+    changes: &CommentChanges,
+) -> Result<Comment> {
+    conn.transaction(|| {
+        let comment = models::find_comment(conn, comment_id)?;
+        allow(can_edit_comment(user, &comment))?;
+        models::update_comment(conn, comment_id, changes)
+    })
+}
+
+pub fn delete_comment(conn: &SqliteConnection, user: &User, comment_id: i32) -> Result<Comment> {
+    conn.transaction(|| {
+        let comment = models::find_comment(conn, comment_id)?;
+        let post = models::find_post(conn, comment.post_id)?;
+        allow(can_delete_comment(user, &comment, &post))?;
+        models::delete_comment(conn, comment_id)
+    })
+}
+
+pub fn set_role(conn: &SqliteConnection, user: &User, user_id: i32, role: Role) -> Result<User> {
+    allow(user.role == Role::Admin)?;
+    conn.transaction(|| {
+        if role != Role::Admin {
+            keep_an_admin(conn, user_id)?;
+        }
+        models::set_role(conn, user_id, role)
+    })
+}
+
+// Only an admin can make another, so the last one can neither be demoted nor deleted.
+fn keep_an_admin(conn: &SqliteConnection, user_id: i32) -> Result<()> {
+    let user = models::find_user(conn, UserKey::ID(user_id))?;
+    if user.role == Role::Admin && models::count_admins(conn)? <= 1 {
+        return Err(AppError::LastAdmin);
+    }
+    Ok(())
+}
@@ -1,10 +1,29 @@
 use crate::errors::AppError;
 use actix_web::HttpResponse;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 
 pub(super) mod comments;
+pub(super) mod feeds;
 pub(super) mod posts;
+pub(super) mod reviews;
+pub(super) mod search;
+pub(super) mod site;
+pub(super) mod tags;
 pub(super) mod users;
 
+// RFC 3986's unreserved characters, which mean the same wherever they are in a URL.
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Percent-encodes text to go into a URL as a path segment or a query value. Usernames and tags
+/// may contain anything, including `/`, `?` and `#`.
+pub(crate) fn urlencode(s: &str) -> String {
+    utf8_percent_encode(s, UNRESERVED).to_string()
+}
+
 fn convert<T, E>(res: Result<T, E>) -> Result<HttpResponse, AppError>
 where
     T: serde::Serialize,
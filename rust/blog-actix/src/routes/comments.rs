@@ -1,50 +1,93 @@
 use crate::errors::AppError;
+use crate::pagination::{ListParams, Page};
 use crate::routes::convert;
-use crate::{models, Pool};
-use actix_web::{web, HttpResponse};
+use crate::{models, permissions, Pool};
+use actix_web::{web, HttpRequest, HttpResponse};
 use diesel::prelude::*;
 use futures::Future;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct CommentInput {
-    user_id: i32,
     body: String,
+    // The comment this one replies to, if any.
+    parent_id: Option<i32>,
 }
 
 fn add_comment(
+    user: models::User,
     post_id: web::Path<i32>,
     comment: web::Json<CommentInput>,
     pool: web::Data<Pool>,
 ) -> impl Future<Item = HttpResponse, Error = AppError> {
     web::block(move || {
         let conn: &SqliteConnection = &pool.get().unwrap();
-        let data = comment.into_inner();
-        let user_id = data.user_id;
-        let body = data.body;
-        models::create_comment(conn, user_id, post_id.into_inner(), body.as_str())
+        let comment = comment.into_inner();
+        permissions::create_comment(
+            conn,
+            &user,
+            post_id.into_inner(),
+            comment.parent_id,
+            comment.body.as_str(),
+        )
+    })
+    .then(convert)
+}
+
+fn update_comment(
+    user: models::User,
+    comment_id: web::Path<i32>,
+    changes: web::Json<models::CommentChanges>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        permissions::update_comment(conn, &user, comment_id.into_inner(), &changes)
+    })
+    .then(convert)
+}
+
+fn delete_comment(
+    user: models::User,
+    comment_id: web::Path<i32>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        permissions::delete_comment(conn, &user, comment_id.into_inner())
     })
     .then(convert)
 }
 
 fn post_comments(
+    req: HttpRequest,
+    user: Option<models::User>,
     post_id: web::Path<i32>,
+    params: web::Query<ListParams>,
     pool: web::Data<Pool>,
 ) -> impl Future<Item = HttpResponse, Error = AppError> {
+    let params = params.into_inner();
+    let query = params.clone();
     web::block(move || {
         let conn: &SqliteConnection = &pool.get().unwrap();
-        models::post_comments(conn, post_id.into_inner())
+        permissions::post_comments(conn, user.as_ref(), post_id.into_inner(), &query)
     })
+    .map(move |comments| Page::new(comments, &params, req.path(), |comment| comment.id))
     .then(convert)
 }
 
 fn user_comments(
+    req: HttpRequest,
     user_id: web::Path<i32>,
+    params: web::Query<ListParams>,
     pool: web::Data<Pool>,
 ) -> impl Future<Item = HttpResponse, Error = AppError> {
+    let params = params.into_inner();
+    let query = params.clone();
     web::block(move || {
         let conn: &SqliteConnection = &pool.get().unwrap();
-        models::user_comments(conn, user_id.into_inner())
+        models::user_comments(conn, user_id.into_inner(), &query)
     })
+    .map(move |comments| Page::new(comments, &params, req.path(), |(comment, _)| comment.id))
     .then(convert)
 }
 
@@ -54,5 +97,10 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             web::resource("/posts/{id}/comments")
                 .route(web::post().to_async(add_comment))
                 .route(web::get().to_async(post_comments)),
+        )
+        .service(
+            web::resource("/comments/{id}")
+                .route(web::patch().to_async(update_comment))
+                .route(web::delete().to_async(delete_comment)),
         );
 }
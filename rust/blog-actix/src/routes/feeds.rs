@@ -0,0 +1,104 @@
+use crate::errors::AppError;
+use crate::feeds::{self, Feed, Site, FEED_LENGTH};
+use crate::models::{self, Status};
+use crate::pagination::ListParams;
+use crate::Pool;
+use actix_web::error::BlockingError;
+use actix_web::http::header::{ETAG, IF_NONE_MATCH};
+use actix_web::{web, HttpRequest, HttpResponse};
+use diesel::prelude::*;
+use futures::Future;
+
+const ATOM: &str = "application/atom+xml; charset=utf-8";
+const RSS: &str = "application/rss+xml; charset=utf-8";
+
+fn feed_params() -> ListParams {
+    ListParams {
+        limit: Some(FEED_LENGTH),
+        status: Some(Status::Published),
+        ..Default::default()
+    }
+}
+
+fn if_none_match(req: &HttpRequest) -> Option<String> {
+    let value = req.headers().get(IF_NONE_MATCH)?.to_str().ok()?;
+    Some(value.to_string())
+}
+
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+// Aggregators poll feeds, so one which sends back the `ETag` of the feed it already has gets an
+// empty 304 unless something has changed since.
+fn respond(
+    if_none_match: Option<String>,
+    content_type: &'static str,
+    res: Result<String, BlockingError<AppError>>,
+) -> Result<HttpResponse, AppError> {
+    let body = res?;
+    let etag = feeds::etag(&body);
+    if let Some(if_none_match) = if_none_match {
+        if etag_matches(&if_none_match, &etag) {
+            return Ok(HttpResponse::NotModified().header(ETAG, etag).finish());
+        }
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .header(ETAG, etag)
+        .body(body))
+}
+
+fn site_feed(pool: &Pool, site: &Site, path: &str) -> Result<Feed, AppError> {
+    let conn: &SqliteConnection = &pool.get().unwrap();
+    let posts = models::all_posts(conn, &feed_params())?;
+    Ok(Feed::site(site, path, posts))
+}
+
+fn rss(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    site: web::Data<Site>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    let if_none_match = if_none_match(&req);
+    let path = req.path().to_owned();
+    web::block(move || site_feed(&pool, &site, &path)?.to_rss())
+        .then(move |res| respond(if_none_match, RSS, res))
+}
+
+fn atom(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    site: web::Data<Site>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    let if_none_match = if_none_match(&req);
+    let path = req.path().to_owned();
+    web::block(move || site_feed(&pool, &site, &path)?.to_atom())
+        .then(move |res| respond(if_none_match, ATOM, res))
+}
+
+fn author_atom(
+    req: HttpRequest,
+    user_id: web::Path<i32>,
+    pool: web::Data<Pool>,
+    site: web::Data<Site>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    let if_none_match = if_none_match(&req);
+    let path = req.path().to_owned();
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        let author = models::find_user(conn, models::UserKey::ID(user_id.into_inner()))?;
+        let posts = models::user_posts(conn, author.id, &feed_params())?;
+        Feed::author(&site, &path, &author, posts).to_atom()
+    })
+    .then(move |res| respond(if_none_match, ATOM, res))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/feed.rss").route(web::get().to_async(rss)))
+        .service(web::resource("/feed.atom").route(web::get().to_async(atom)))
+        .service(web::resource("/users/{id}/feed.atom").route(web::get().to_async(author_atom)));
+}
@@ -1,7 +1,9 @@
 use crate::errors::AppError;
+use crate::models::Status;
+use crate::pagination::{ListParams, Page};
 use crate::routes::convert;
-use crate::{models, Pool};
-use actix_web::{web, HttpResponse};
+use crate::{models, permissions, Pool};
+use actix_web::{web, HttpRequest, HttpResponse};
 use diesel::prelude::*;
 use futures::Future;
 
@@ -9,62 +11,155 @@ use futures::Future;
 struct PostInput {
     title: String,
     body: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+// Leaving out `tags` keeps the ones the post has, while an empty list removes them all.
+#[derive(Debug, Deserialize)]
+struct PostUpdate {
+    #[serde(flatten)]
+    changes: models::PostChanges,
+    tags: Option<Vec<String>>,
 }
 
 fn add_post(
-    user_id: web::Path<i32>,
+    user: models::User,
     post: web::Json<PostInput>,
     pool: web::Data<Pool>,
 ) -> impl Future<Item = HttpResponse, Error = AppError> {
     web::block(move || {
         let conn: &SqliteConnection = &pool.get().unwrap();
-        let key = models::UserKey::ID(user_id.into_inner());
-        models::find_user(conn, key).and_then(|user| {
-            let post = post.into_inner();
-            let title = post.title;
-            let body = post.body;
-            models::create_post(conn, &user, title.as_str(), body.as_str())
-        })
+        let post = post.into_inner();
+        let title = post.title;
+        let body = post.body;
+        models::create_post(conn, &user, title.as_str(), body.as_str(), &post.tags)
+    })
+    .then(convert)
+}
+
+fn update_post(
+    user: models::User,
+    post_id: web::Path<i32>,
+    update: web::Json<PostUpdate>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        let update = update.into_inner();
+        permissions::update_post(
+            conn,
+            &user,
+            post_id.into_inner(),
+            &update.changes,
+            update.tags.as_deref(),
+        )
+    })
+    .then(convert)
+}
+
+fn delete_post(
+    user: models::User,
+    post_id: web::Path<i32>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        permissions::delete_post(conn, &user, post_id.into_inner())
+    })
+    .then(convert)
+}
+
+fn request_review(
+    user: models::User,
+    post_id: web::Path<i32>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        permissions::set_status(conn, &user, post_id.into_inner(), Status::PendingReview)
     })
     .then(convert)
 }
 
 fn publish_post(
+    user: models::User,
+    post_id: web::Path<i32>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        permissions::set_status(conn, &user, post_id.into_inner(), Status::Published)
+    })
+    .then(convert)
+}
+
+// Taking a post down archives it rather than making it a draft again.
+fn unpublish_post(
+    user: models::User,
     post_id: web::Path<i32>,
     pool: web::Data<Pool>,
 ) -> impl Future<Item = HttpResponse, Error = AppError> {
     web::block(move || {
         let conn: &SqliteConnection = &pool.get().unwrap();
-        models::publish_post(conn, post_id.into_inner())
+        permissions::set_status(conn, &user, post_id.into_inner(), Status::Archived)
     })
     .then(convert)
 }
 
+// Everyone sees an author's published posts, and only the author and admins see the rest.
 fn user_posts(
+    req: HttpRequest,
+    user: Option<models::User>,
     user_id: web::Path<i32>,
+    params: web::Query<ListParams>,
     pool: web::Data<Pool>,
 ) -> impl Future<Item = HttpResponse, Error = AppError> {
+    let params = params.into_inner();
+    let mut query = params.clone();
+    if !user.iter().any(|user| permissions::can_manage_posts_of(user, *user_id)) {
+        query.status = Some(Status::Published);
+    }
     web::block(move || {
         let conn: &SqliteConnection = &pool.get().unwrap();
-        models::user_posts(conn, user_id.into_inner())
+        models::user_posts(conn, user_id.into_inner(), &query)
     })
+    .map(move |posts| Page::new(posts, &params, req.path(), |(post, _, _)| post.id))
     .then(convert)
 }
 
-fn all_posts(pool: web::Data<Pool>) -> impl Future<Item = HttpResponse, Error = AppError> {
+fn all_posts(
+    req: HttpRequest,
+    params: web::Query<ListParams>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    let params = params.into_inner();
+    let query = params.clone();
     web::block(move || {
         let conn: &SqliteConnection = &pool.get().unwrap();
-        models::all_posts(conn)
+        models::all_posts(conn, &query)
     })
+    .map(move |posts| Page::new(posts, &params, req.path(), |((post, _), _, _)| post.id))
     .then(convert)
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::resource("/users/{id}/posts")
-            .route(web::post().to_async(add_post))
-            .route(web::get().to_async(user_posts)),
-    )
-    .service(web::resource("/posts").route(web::get().to_async(all_posts)))
-    .service(web::resource("/posts/{id}/publish").route(web::post().to_async(publish_post)));
+    cfg.service(web::resource("/users/{id}/posts").route(web::get().to_async(user_posts)))
+        .service(
+            web::resource("/posts")
+                .route(web::post().to_async(add_post))
+                .route(web::get().to_async(all_posts)),
+        )
+        .service(
+            web::resource("/posts/{id}")
+                .route(web::patch().to_async(update_post))
+                .route(web::delete().to_async(delete_post)),
+        )
+        .service(
+            web::resource("/posts/{id}/request-review").route(web::post().to_async(request_review)),
+        )
+        .service(web::resource("/posts/{id}/publish").route(web::post().to_async(publish_post)))
+        .service(
+            web::resource("/posts/{id}/unpublish").route(web::post().to_async(unpublish_post)),
+        );
 }
@@ -0,0 +1,81 @@
+use crate::errors::AppError;
+use crate::pagination::{ListParams, Page};
+use crate::routes::convert;
+use crate::{models, permissions, Pool};
+use actix_web::{web, HttpRequest, HttpResponse};
+use diesel::prelude::*;
+use futures::Future;
+
+// The body is optional, so an approval can be a bare POST.
+#[derive(Debug, Deserialize)]
+struct ReviewInput {
+    note: Option<String>,
+}
+
+fn review(
+    user: models::User,
+    post_id: web::Path<i32>,
+    input: Option<web::Json<ReviewInput>>,
+    pool: web::Data<Pool>,
+    approved: bool,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    let note = input.and_then(|input| input.into_inner().note);
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        permissions::review_post(conn, &user, post_id.into_inner(), approved, note.as_deref())
+    })
+    .then(convert)
+}
+
+fn approve(
+    user: models::User,
+    post_id: web::Path<i32>,
+    input: Option<web::Json<ReviewInput>>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    review(user, post_id, input, pool, true)
+}
+
+fn reject(
+    user: models::User,
+    post_id: web::Path<i32>,
+    input: Option<web::Json<ReviewInput>>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    review(user, post_id, input, pool, false)
+}
+
+fn post_reviews(
+    user: models::User,
+    post_id: web::Path<i32>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        permissions::post_reviews(conn, &user, post_id.into_inner())
+    })
+    .then(convert)
+}
+
+fn review_queue(
+    req: HttpRequest,
+    user: models::User,
+    params: web::Query<ListParams>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    let params = params.into_inner();
+    let query = params.clone();
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        permissions::review_queue(conn, &user, &query)
+    })
+    .map(move |posts| Page::new(posts, &params, req.path(), |((post, _), _, _)| post.id))
+    .then(convert)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/review-queue").route(web::get().to_async(review_queue)))
+        .service(web::resource("/posts/{id}/approve").route(web::post().to_async(approve)))
+        .service(web::resource("/posts/{id}/reject").route(web::post().to_async(reject)))
+        .service(web::resource("/posts/{id}/reviews").route(web::get().to_async(post_reviews)));
+}
@@ -0,0 +1,28 @@
+use crate::errors::AppError;
+use crate::pagination::clamp_limit;
+use crate::routes::convert;
+use crate::{search, Pool};
+use actix_web::{web, HttpResponse};
+use diesel::prelude::*;
+use futures::Future;
+
+#[derive(Debug, Deserialize)]
+struct SearchParams {
+    q: String,
+    limit: Option<i64>,
+}
+
+fn search(
+    params: web::Query<SearchParams>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        search::search(conn, &params.q, clamp_limit(params.limit))
+    })
+    .then(convert)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/search").route(web::get().to_async(search)));
+}
@@ -0,0 +1,219 @@
+use crate::errors::AppError;
+use crate::models::{self, CommentNode, Post, Status, Tag, User};
+use crate::pagination::{ListParams, Page};
+use crate::{markdown, Pool};
+use actix_web::error::BlockingError;
+use actix_web::{web, HttpRequest, HttpResponse};
+use askama::Template;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use futures::Future;
+use percent_encoding::percent_decode_str;
+
+// The read-only HTML side of the blog. It shows only published posts, and uses the same
+// queries as the JSON routes. Templates live in `templates/` and are compiled in, so only the
+// files under `static/` are read at run time, from the directory the server was started in.
+
+/// How many top level comments a post page shows.
+const COMMENTS_PER_PAGE: i64 = 100;
+
+struct PostSummary {
+    id: i32,
+    title: String,
+    body_html: String,
+    author: String,
+    date: String,
+    tags: Vec<String>,
+    comment_count: usize,
+}
+
+impl PostSummary {
+    fn new(post: Post, author: &User, tags: Vec<Tag>, comment_count: usize) -> Self {
+        PostSummary {
+            id: post.id,
+            body_html: markdown::to_html(&post.body),
+            title: post.title,
+            author: author.username.clone(),
+            date: format_date(&post.created_at),
+            tags: tags.into_iter().map(|tag| tag.name).collect(),
+            comment_count,
+        }
+    }
+}
+
+// A comment tree flattened into the order it reads in, with the depth kept for indenting.
+struct CommentView {
+    depth: usize,
+    author: Option<String>,
+    body: Option<String>,
+    date: String,
+}
+
+fn flatten_comments(nodes: Vec<CommentNode>, depth: usize, out: &mut Vec<CommentView>) {
+    for node in nodes {
+        out.push(CommentView {
+            depth,
+            author: node.user.map(|user| user.username),
+            body: node.body,
+            date: format_date(&node.created_at),
+        });
+        flatten_comments(node.replies, depth + 1, out);
+    }
+}
+
+// Filters the templates can use, which askama looks for in this module.
+mod filters {
+    pub fn urlencode<T: std::fmt::Display>(s: T) -> askama::Result<String> {
+        Ok(crate::routes::urlencode(&s.to_string()))
+    }
+}
+
+fn format_date(date: &NaiveDateTime) -> String {
+    date.format("%B %-d, %Y").to_string()
+}
+
+#[derive(Template)]
+#[template(path = "index.html")]
+struct IndexPage {
+    tag: Option<String>,
+    posts: Vec<PostSummary>,
+    next: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "post.html")]
+struct PostPage {
+    post: PostSummary,
+    comments: Vec<CommentView>,
+}
+
+#[derive(Template)]
+#[template(path = "author.html")]
+struct AuthorPage {
+    author: String,
+    posts: Vec<PostSummary>,
+    next: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "not_found.html")]
+struct NotFoundPage;
+
+fn render<T: Template>(page: &T) -> Result<String, AppError> {
+    page.render().map_err(AppError::TemplateError)
+}
+
+// Like `convert`, but for pages. A missing record gets an HTML page rather than JSON, as a
+// reader following a stale link should not be shown an API error.
+fn html(res: Result<String, BlockingError<AppError>>) -> Result<HttpResponse, AppError> {
+    let (mut builder, body) = match res.map_err(AppError::from) {
+        Ok(body) => (HttpResponse::Ok(), body),
+        Err(AppError::RecordNotFound) => (HttpResponse::NotFound(), render(&NotFoundPage)?),
+        Err(e) => return Err(e),
+    };
+    Ok(builder.content_type("text/html; charset=utf-8").body(body))
+}
+
+fn index(
+    req: HttpRequest,
+    params: web::Query<ListParams>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    let params = params.into_inner();
+    let path = req.path().to_owned();
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        let posts = models::all_posts(conn, &params)?;
+        let page = Page::new(posts, &params, &path, |((post, _), _, _)| post.id);
+        let posts = page
+            .items
+            .into_iter()
+            .map(|((post, author), comments, tags)| {
+                PostSummary::new(post, &author, tags, comments.count as usize)
+            })
+            .collect();
+        render(&IndexPage {
+            tag: params.tag.clone(),
+            posts,
+            next: page.next,
+        })
+    })
+    .then(html)
+}
+
+fn post(
+    post_id: web::Path<i32>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        let post = models::find_post(conn, post_id.into_inner())?;
+        if post.status != Status::Published {
+            return Err(AppError::RecordNotFound);
+        }
+        let author = models::find_user(conn, models::UserKey::ID(post.user_id))?;
+        let tags = models::post_tags(conn, post.id)?;
+        let params = ListParams {
+            limit: Some(COMMENTS_PER_PAGE),
+            ..Default::default()
+        };
+        let mut tree = models::post_comments(conn, post.id, &params)?;
+        // There is no link to more comments, so the extra one fetched to find out whether there
+        // are any is dropped.
+        tree.truncate(COMMENTS_PER_PAGE as usize);
+
+        let mut comments = Vec::new();
+        flatten_comments(tree, 0, &mut comments);
+        let comment_count = comments.iter().filter(|c| c.body.is_some()).count();
+        render(&PostPage {
+            post: PostSummary::new(post, &author, tags, comment_count),
+            comments,
+        })
+    })
+    .then(html)
+}
+
+fn author(
+    req: HttpRequest,
+    params: web::Query<ListParams>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    let params = params.into_inner();
+    let path = req.path().to_owned();
+    // The router leaves `%2F` and `%2B` encoded in what it matches, so the username is decoded
+    // from the path as it was sent.
+    let username = path.rsplit('/').next().unwrap_or_default();
+    let username = percent_decode_str(username)
+        .decode_utf8_lossy()
+        .into_owned();
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        let author = models::find_user(conn, models::UserKey::Username(&username))?;
+        let query = ListParams {
+            status: Some(Status::Published),
+            ..params.clone()
+        };
+        let posts = models::user_posts(conn, author.id, &query)?;
+        let page = Page::new(posts, &params, &path, |(post, _, _)| post.id);
+        let posts = page
+            .items
+            .into_iter()
+            .map(|(post, comments, tags)| {
+                PostSummary::new(post, &author, tags, comments.count as usize)
+            })
+            .collect();
+        render(&AuthorPage {
+            author: author.username.clone(),
+            posts,
+            next: page.next,
+        })
+    })
+    .then(html)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/").route(web::get().to_async(index)))
+        .service(web::resource("/post/{id}").route(web::get().to_async(post)))
+        .service(web::resource("/author/{username}").route(web::get().to_async(author)))
+        .service(actix_files::Files::new("/static", "static"));
+}
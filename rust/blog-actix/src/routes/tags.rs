@@ -0,0 +1,40 @@
+use crate::errors::AppError;
+use crate::pagination::{ListParams, Page};
+use crate::routes::convert;
+use crate::{models, Pool};
+use actix_web::{web, HttpRequest, HttpResponse};
+use diesel::prelude::*;
+use futures::Future;
+
+fn all_tags(pool: web::Data<Pool>) -> impl Future<Item = HttpResponse, Error = AppError> {
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        models::tag_counts(conn)
+    })
+    .then(convert)
+}
+
+// The same list as `/posts`, narrowed to one tag.
+fn tag_posts(
+    req: HttpRequest,
+    name: web::Path<String>,
+    params: web::Query<ListParams>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    let params = params.into_inner();
+    let query = ListParams {
+        tag: Some(name.into_inner()),
+        ..params.clone()
+    };
+    web::block(move || {
+        let conn: &SqliteConnection = &pool.get().unwrap();
+        models::all_posts(conn, &query)
+    })
+    .map(move |posts| Page::new(posts, &params, req.path(), |((post, _), _, _)| post.id))
+    .then(convert)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/tags").route(web::get().to_async(all_tags)))
+        .service(web::resource("/tags/{name}/posts").route(web::get().to_async(tag_posts)));
+}
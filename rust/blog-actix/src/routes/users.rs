@@ -1,12 +1,34 @@
+use crate::auth::{self, Tokens};
 use crate::errors::AppError;
 use crate::routes::convert;
-use crate::{models, Pool};
+use crate::{models, permissions, Pool};
 use actix_web::{web, HttpResponse};
 use futures::Future;
 
-#[derive(Debug, Serialize, Deserialize)]
+// Registration and login take the same fields. There is no `Debug` so that the password
+// cannot end up in a log line.
+#[derive(Deserialize)]
 struct UserInput {
     username: String,
+    password: String,
+}
+
+// Either field may be left out to keep its current value.
+#[derive(Deserialize)]
+struct UserUpdate {
+    username: Option<String>,
+    password: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RoleInput {
+    role: models::Role,
+}
+
+#[derive(Serialize)]
+struct LoginResponse {
+    token: String,
+    user: models::User,
 }
 
 // The return value is `impl Future` which means it is a type that will implement
@@ -21,8 +43,36 @@ fn create_user(
     // We do this since Diesel does not have support for Futures.
     web::block(move || {
         let conn = &pool.get().unwrap();
-        let username = item.into_inner().username;
-        models::create_user(conn, username.as_str())
+        let input = item.into_inner();
+        let password_hash = auth::hash_password(&input.password)?;
+        models::create_user(conn, input.username.as_str(), password_hash.as_str())
+    })
+    .then(convert)
+}
+
+// Hashing is deliberately slow, so the password is checked on the thread pool along with the
+// query. An unknown username and a wrong password give the same error after the same time.
+fn login(
+    item: web::Json<UserInput>,
+    pool: web::Data<Pool>,
+    tokens: web::Data<Tokens>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    web::block(move || {
+        let conn = &pool.get().unwrap();
+        let input = item.into_inner();
+        let (user, password_hash, token_version) =
+            match models::find_credentials(conn, &input.username) {
+                Err(AppError::RecordNotFound) => {
+                    auth::reject_password(&input.password);
+                    return Err(AppError::InvalidCredentials);
+                }
+                result => result?,
+            };
+        if !auth::verify_password(&password_hash, &input.password) {
+            return Err(AppError::InvalidCredentials);
+        }
+        let token = tokens.issue(user.id, token_version);
+        Ok(LoginResponse { token, user })
     })
     .then(convert)
 }
@@ -53,8 +103,62 @@ fn get_user(
     .then(convert)
 }
 
+fn update_user(
+    user: models::User,
+    user_id: web::Path<i32>,
+    item: web::Json<UserUpdate>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    web::block(move || {
+        let conn = &pool.get().unwrap();
+        let input = item.into_inner();
+        let password_hash = match input.password {
+            Some(password) => Some(auth::hash_password(&password)?),
+            None => None,
+        };
+        let changes = models::UserChanges {
+            username: input.username,
+            password_hash,
+        };
+        permissions::update_user(conn, &user, user_id.into_inner(), &changes)
+    })
+    .then(convert)
+}
+
+fn delete_user(
+    user: models::User,
+    user_id: web::Path<i32>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    web::block(move || {
+        let conn = &pool.get().unwrap();
+        permissions::delete_user(conn, &user, user_id.into_inner())
+    })
+    .then(convert)
+}
+
+fn set_role(
+    user: models::User,
+    user_id: web::Path<i32>,
+    item: web::Json<RoleInput>,
+    pool: web::Data<Pool>,
+) -> impl Future<Item = HttpResponse, Error = AppError> {
+    web::block(move || {
+        let conn = &pool.get().unwrap();
+        permissions::set_role(conn, &user, user_id.into_inner(), item.into_inner().role)
+    })
+    .then(convert)
+}
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(web::resource("/users").route(web::post().to_async(create_user)))
+        .service(web::resource("/login").route(web::post().to_async(login)))
         .service(web::resource("/users/find/{name}").route(web::get().to_async(find_user)))
-        .service(web::resource("/users/{id}").route(web::get().to_async(get_user)));
+        .service(
+            web::resource("/users/{id}")
+                .route(web::get().to_async(get_user))
+                .route(web::patch().to_async(update_user))
+                .route(web::delete().to_async(delete_user)),
+        )
+        .service(web::resource("/users/{id}/role").route(web::put().to_async(set_role)));
 }
@@ -4,6 +4,17 @@ table! {
         user_id -> Integer,
         post_id -> Integer,
         body -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        deleted_at -> Nullable<Timestamp>,
+        parent_id -> Nullable<Integer>,
+    }
+}
+
+table! {
+    post_tags (post_id, tag_id) {
+        post_id -> Integer,
+        tag_id -> Integer,
     }
 }
 
@@ -13,7 +24,27 @@ table! {
         user_id -> Integer,
         title -> Text,
         body -> Text,
-        published -> Bool,
+        status -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    reviews (id) {
+        id -> Integer,
+        post_id -> Integer,
+        reviewer_id -> Integer,
+        approved -> Bool,
+        note -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    tags (id) {
+        id -> Integer,
+        name -> Text,
     }
 }
 
@@ -21,15 +52,20 @@ table! {
     users (id) {
         id -> Integer,
         username -> Text,
+        password_hash -> Text,
+        role -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        token_version -> Integer,
     }
 }
 
 joinable!(comments -> posts (post_id));
 joinable!(comments -> users (user_id));
+joinable!(post_tags -> posts (post_id));
+joinable!(post_tags -> tags (tag_id));
 joinable!(posts -> users (user_id));
+joinable!(reviews -> posts (post_id));
+joinable!(reviews -> users (reviewer_id));
 
-allow_tables_to_appear_in_same_query!(
-    comments,
-    posts,
-    users,
-);
+allow_tables_to_appear_in_same_query!(comments, post_tags, posts, reviews, tags, users,);
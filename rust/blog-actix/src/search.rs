@@ -0,0 +1,145 @@
+use crate::errors::AppError;
+use askama::{Html, MarkupDisplay};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Double, Integer, Text};
+
+type Result<T> = std::result::Result<T, AppError>;
+
+// The virtual tables are not in `schema.rs`, Diesel's DSL has no notion of `MATCH`, and the
+// ranking and snippets come from FTS5 functions, so these queries are written as SQL. Titles
+// and snippets come back as HTML: the text is escaped and matching terms are wrapped in `<mark>`
+// tags, so they can go into a page as they are.
+
+// FTS5 marks where a match starts and ends with these, as the text itself could contain tags.
+// They are swapped for `<mark>` tags once the text around them has been escaped, which is only
+// safe because `strip_markers` keeps them out of everything that is stored.
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+const POSTS_QUERY: &str = "
+    SELECT posts.id, posts.user_id, users.username,
+        highlight(posts_fts, 0, char(2), char(3)) AS title,
+        snippet(posts_fts, 1, char(2), char(3), '…', 16) AS snippet,
+        bm25(posts_fts, 10.0, 1.0) AS rank
+    FROM posts_fts
+    JOIN posts ON posts.id = posts_fts.rowid
+    JOIN users ON users.id = posts.user_id
+    WHERE posts_fts MATCH ? AND posts.status = 'published'
+    ORDER BY rank
+    LIMIT ?";
+
+// A deleted comment stays in the index until the row itself goes, so it is filtered out here,
+// as are comments on posts which are not published.
+const COMMENTS_QUERY: &str = "
+    SELECT comments.id, comments.post_id, comments.user_id, users.username,
+        snippet(comments_fts, 0, char(2), char(3), '…', 16) AS snippet,
+        bm25(comments_fts) AS rank
+    FROM comments_fts
+    JOIN comments ON comments.id = comments_fts.rowid
+    JOIN posts ON posts.id = comments.post_id
+    JOIN users ON users.id = comments.user_id
+    WHERE comments_fts MATCH ? AND comments.deleted_at IS NULL AND posts.status = 'published'
+    ORDER BY rank
+    LIMIT ?";
+
+#[derive(QueryableByName, Serialize, Debug)]
+pub struct PostHit {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    #[sql_type = "Integer"]
+    pub user_id: i32,
+    #[sql_type = "Text"]
+    pub username: String,
+    #[sql_type = "Text"]
+    pub title: String,
+    #[sql_type = "Text"]
+    pub snippet: String,
+    // Lower is better, as with SQLite's own `bm25`.
+    #[sql_type = "Double"]
+    pub rank: f64,
+}
+
+#[derive(QueryableByName, Serialize, Debug)]
+pub struct CommentHit {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    #[sql_type = "Integer"]
+    pub post_id: i32,
+    #[sql_type = "Integer"]
+    pub user_id: i32,
+    #[sql_type = "Text"]
+    pub username: String,
+    #[sql_type = "Text"]
+    pub snippet: String,
+    #[sql_type = "Double"]
+    pub rank: f64,
+}
+
+// Scores from the two tables are not comparable, so each list is ranked on its own.
+#[derive(Serialize, Debug, Default)]
+pub struct SearchResults {
+    pub posts: Vec<PostHit>,
+    pub comments: Vec<CommentHit>,
+}
+
+/// Turns what was typed into an FTS5 query in which every word must appear.
+///
+/// Each word is quoted, so punctuation and FTS5 operators in the input are matched as text
+/// rather than failing to parse. A word ending in `*` is kept as a prefix search.
+fn match_expression(q: &str) -> Option<String> {
+    let terms: Vec<String> = q
+        .split_whitespace()
+        .filter_map(|word| {
+            let prefix = word.ends_with('*');
+            let word = word.trim_end_matches('*');
+            if word.is_empty() {
+                return None;
+            }
+            let quoted = format!("\"{}\"", word.replace('"', "\"\""));
+            Some(if prefix { quoted + "*" } else { quoted })
+        })
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Removes the characters matches are marked with, so that any in a title or snippet were put
+/// there by FTS5. Titles, post bodies and comments go through this before they are stored.
+pub fn strip_markers(text: &str) -> String {
+    text.replace(&[MATCH_START, MATCH_END][..], "")
+}
+
+fn mark_matches(text: &str) -> String {
+    MarkupDisplay::new_unsafe(text, Html)
+        .to_string()
+        .replace(MATCH_START, "<mark>")
+        .replace(MATCH_END, "</mark>")
+}
+
+pub fn search(conn: &SqliteConnection, q: &str, limit: i64) -> Result<SearchResults> {
+    let expression = match match_expression(q) {
+        Some(expression) => expression,
+        None => return Ok(SearchResults::default()),
+    };
+    let mut posts = sql_query(POSTS_QUERY)
+        .bind::<Text, _>(&expression)
+        .bind::<BigInt, _>(limit)
+        .load::<PostHit>(conn)?;
+    let mut comments = sql_query(COMMENTS_QUERY)
+        .bind::<Text, _>(&expression)
+        .bind::<BigInt, _>(limit)
+        .load::<CommentHit>(conn)?;
+    for hit in posts.iter_mut() {
+        hit.title = mark_matches(&hit.title);
+        hit.snippet = mark_matches(&hit.snippet);
+    }
+    for hit in comments.iter_mut() {
+        hit.snippet = mark_matches(&hit.snippet);
+    }
+
+    Ok(SearchResults { posts, comments })
+}
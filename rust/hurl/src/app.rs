@@ -1,11 +1,19 @@
 use log::{debug, trace};
+use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
 use crate::config;
+use crate::encoding::Encoding;
 use crate::errors::{Error, HurlResult};
+use crate::history;
+use crate::openapi;
+use crate::retry::RetryCondition;
+use crate::schema;
 use crate::session::make_safe_pathname;
+use crate::transport::Resolve;
+use crate::watch::{Condition, Interval};
 
 /// A command line HTTP client
 #[derive(StructOpt, Debug)]
@@ -30,7 +38,14 @@ pub struct App {
     /// A string of the form `username:password`. If only
     /// `username` is given then you will be prompted
     /// for a password. If you wish to use no password
-    /// then use the form `username:`.
+    /// then use the form `username:`. The password is taken
+    /// from ~/.netrc instead of prompting when it has an entry
+    /// for the host with that login.
+    ///
+    /// Without --auth, --token or an Authorization header from the
+    /// parameters or the session, credentials for the host are
+    /// looked up with the profile's credential helper and then
+    /// in ~/.netrc, or the file named by NETRC.
     #[structopt(short, long)]
     pub auth: Option<String>,
 
@@ -53,6 +68,48 @@ pub struct App {
     #[structopt(long)]
     pub read_only: bool,
 
+    /// Compare the response to a baseline saved with --save-baseline.
+    ///
+    /// The status, headers and body are compared and each difference is
+    /// printed after the response. The body is compared structurally if it
+    /// is JSON. The exit code is nonzero if there are differences.
+    #[structopt(long, parse(from_os_str))]
+    pub compare_to: Option<PathBuf>,
+
+    /// Save the status, headers and body of the response as a baseline
+    /// for --compare-to.
+    #[structopt(long, parse(from_os_str))]
+    pub save_baseline: Option<PathBuf>,
+
+    /// Validate the response body against a JSON Schema draft-07 document.
+    ///
+    /// Each violation is printed with the JSON pointer of the offending
+    /// value, and the exit code is nonzero if there are any.
+    #[structopt(long, parse(from_os_str))]
+    pub schema: Option<PathBuf>,
+
+    /// The document given with --schema.
+    #[structopt(skip)]
+    pub response_schema: Option<serde_json::Value>,
+
+    /// Keys to ignore when comparing responses, as a comma separated list.
+    ///
+    /// A key such as request_id is ignored at any depth of the body and
+    /// also matches a header with that name. A JSON pointer such as
+    /// /meta/generated_at ignores only that value. The Date and
+    /// Content-Length headers are always ignored.
+    #[structopt(long, use_delimiter = true, number_of_values = 1)]
+    pub ignore: Vec<String>,
+
+    /// Cache responses in the session.
+    ///
+    /// The ETag and Last-Modified headers of each GET response are stored
+    /// with its body. The next request to the same URL sends them back as
+    /// If-None-Match and If-Modified-Since, and if the server answers with
+    /// 304 Not Modified the cached body is shown. Requires --session.
+    #[structopt(long)]
+    pub cache: bool,
+
     /// Default transport.
     ///
     /// If a URL is given without a transport, i.e. example.com/foo
@@ -61,9 +118,123 @@ pub struct App {
     #[structopt(short, long)]
     pub secure: bool,
 
-    /// The HTTP Method to use, one of: HEAD, GET, POST, PUT, PATCH, DELETE
+    /// Send requests over a Unix domain socket instead of TCP.
+    ///
+    /// The host of the URL is only used for the Host header, so the
+    /// localhost shorthand works well, e.g.
+    /// hurl --unix-socket /var/run/docker.sock GET :/containers/json
+    #[structopt(long, parse(from_os_str))]
+    pub unix_socket: Option<PathBuf>,
+
+    /// Connect to another address for a host and port, as host:port:address.
+    ///
+    /// The Host header still names the host, e.g.
+    /// --resolve example.com:443:10.0.0.5 reaches one backend behind a
+    /// load balancer. May be given more than once. For https the
+    /// certificate is verified against the host as usual.
+    #[structopt(long, number_of_values = 1)]
+    pub resolve: Vec<Resolve>,
+
+    /// Only speak HTTP/1.1.
+    ///
+    /// This is what is used unless --http2 or --http2-prior-knowledge is
+    /// given, as the default TLS backend does not offer HTTP/2.
+    #[structopt(long = "http1.1", conflicts_with_all = &["http2", "http2-prior-knowledge"])]
+    pub http1_1: bool,
+
+    /// Offer HTTP/2 to https servers with ALPN, falling back to HTTP/1.1.
+    ///
+    /// This uses the rustls TLS backend, which verifies certificates
+    /// against the bundled Mozilla roots rather than the system store.
+    /// Plain http URLs are still sent with HTTP/1.1, since upgrading to
+    /// h2c is not supported; use --http2-prior-knowledge for those.
+    #[structopt(long, conflicts_with = "http2-prior-knowledge")]
+    pub http2: bool,
+
+    /// Speak HTTP/2 without negotiating it first.
+    ///
+    /// For plain http URLs this is h2c, as served by gRPC-gateway and
+    /// other local HTTP/2 services. For https URLs only h2 is offered.
+    #[structopt(long)]
+    pub http2_prior_knowledge: bool,
+
+    /// Compress the request body, one of gzip, br or deflate.
+    ///
+    /// The body is sent with a matching Content-Encoding header. It is
+    /// validated against the OpenAPI spec before it is compressed, and
+    /// signed after. Multipart bodies cannot be compressed.
+    #[structopt(long)]
+    pub compress: Option<Encoding>,
+
+    /// Do not decompress the response as it is received.
+    ///
+    /// The Content-Encoding and Content-Length headers are shown as the
+    /// server sent them, with the size of the decoded body alongside.
+    /// Accept-Encoding is sent as "gzip, deflate, br" unless a header
+    /// parameter sets it, e.g. Accept-Encoding:br.
+    #[structopt(long)]
+    pub raw: bool,
+
+    /// Number of times to retry a request which fails transiently.
+    ///
+    /// Which failures count as transient is controlled by --retry-on.
+    /// Each attempt is logged at the info level.
+    #[structopt(long, default_value = "0")]
+    pub retry: u32,
+
+    /// Conditions which trigger a retry, as a comma separated list.
+    ///
+    /// Each entry is either a status code, `timeout`, or `error` for
+    /// any other failure to get a response, e.g. 502,503,timeout.
+    /// Defaults to 429,502,503,504,timeout.
+    #[structopt(long, use_delimiter = true, number_of_values = 1)]
+    pub retry_on: Vec<RetryCondition>,
+
+    /// Initial delay between retries in milliseconds.
+    ///
+    /// The delay doubles with each attempt and is partially randomized.
+    /// A Retry-After header sent by the server takes precedence.
+    #[structopt(long, default_value = "500")]
+    pub retry_delay: u64,
+
+    /// Repeat the request on an interval such as 5s, 500ms or 2m.
+    ///
+    /// The response is redrawn in place after each poll and lines which
+    /// changed since the previous poll are marked with ~, or + if they are
+    /// new. Keys given with --ignore are not marked. A failed poll is shown
+    /// and watching continues.
+    #[structopt(long)]
+    pub watch: Option<Interval>,
+
+    /// Stop watching once the response satisfies a condition.
+    ///
+    /// The condition compares status, headers or json to a JSON value,
+    /// e.g. 'json.state == "done"', 'json.items[0].count >= 10' or
+    /// 'status == 200'. A value on its own is true if it is present and
+    /// not false, 0 or "".
+    #[structopt(long, requires = "watch")]
+    pub until: Option<Condition>,
+
+    /// Do not record this request in the history.
+    ///
+    /// Requests are recorded with their parameters, so pass this when a
+    /// parameter holds a secret. Set history = false in the configuration
+    /// file to never record requests.
+    #[structopt(long)]
+    pub no_history: bool,
+
+    /// Do not validate against the OpenAPI spec of the profile.
+    ///
+    /// By default a request is checked against its operation before it
+    /// is sent, and the response is checked against the responses the
+    /// operation declares.
+    #[structopt(long)]
+    pub no_validate: bool,
+
+    /// The HTTP Method to use, one of: HEAD, GET, POST, PUT, PATCH, DELETE,
+    /// or the diff, ops, history or replay command.
     #[structopt(subcommand)]
-    pub cmd: Option<Method>,
+    pub cmd: Option<Command>,
 
     /// The URL to issue a request to if a method subcommand is not specified.
     pub url: Option<String>,
@@ -79,6 +250,9 @@ pub struct App {
     /// auth: string
     /// token: string
     /// secure: bool
+    /// cache: bool
+    /// ignore: array of strings
+    /// history: bool
     ///
     /// Each option has the same meaning as the corresponding configuration
     /// option with the sae name. The verbose setting is a number from 0
@@ -86,6 +260,50 @@ pub struct App {
     #[structopt(short, long, env = "HURL_CONFIG", parse(from_os_str))]
     pub config: Option<PathBuf>,
 
+    /// Configuration profile.
+    ///
+    /// Profiles are tables in the configuration file named
+    /// [profiles.NAME] which hold settings for a particular service.
+    /// A profile may configure a signer which adds signature headers
+    /// computed from the final request:
+    ///
+    /// [profiles.partner.signer]
+    /// type = "hmac"
+    /// key_id = "abc"
+    /// secret = "..."
+    ///
+    /// [profiles.minio.signer]
+    /// type = "aws-sigv4"
+    /// access_key = "..."
+    /// secret_key = "..."
+    /// region = "us-east-1"
+    /// service = "s3"
+    ///
+    /// A profile may also name an OpenAPI 3 document, in JSON or YAML,
+    /// relative to the configuration file. Requests and responses are
+    /// validated against it and `hurl ops` lists its operations:
+    ///
+    /// [profiles.petstore]
+    /// openapi = "petstore.yaml"
+    ///
+    /// A profile may run a credential helper, a shell command
+    /// which prints a bearer token on stdout, instead of keeping
+    /// the token in the configuration file. HURL_HOST and
+    /// HURL_URL are set to the request being made:
+    ///
+    /// [profiles.gcp]
+    /// credential_helper = "gcloud auth print-access-token"
+    #[structopt(long, env = "HURL_PROFILE")]
+    pub profile: Option<String>,
+
+    /// The settings of the profile selected with --profile.
+    #[structopt(skip)]
+    pub profile_config: config::Profile,
+
+    /// The OpenAPI spec of the profile selected with --profile.
+    #[structopt(skip)]
+    pub openapi: Option<openapi::Spec>,
+
     /// The parameters for the request if a method subcommand is not specified.
     ///
     /// There are seven types of parameters that can be added to a command-line.
@@ -98,7 +316,11 @@ pub struct App {
     ///
     /// File upload -- key@filename
     ///
-    ///   this simulates a file upload via multipart/form-data nd requires --form
+    ///   this simulates a file upload via multipart/form-data. Use - as the
+    ///   filename to read from stdin. Options may follow the filename, e.g.
+    ///   photo@me.png;type=image/png;filename=avatar.png sets the MIME type
+    ///   and the file name sent to the server. Other parameters with data
+    ///   become parts of the same body, with raw JSON sent as application/json.
     ///
     /// Query parameter -- key==value
     ///
@@ -119,6 +341,11 @@ pub struct App {
     /// Raw JSON data from file -- key:=@filename
     ///
     ///   e.g. foo:=@bar.json becomes {"foo":{"bar":"this is from bar.json"}}
+    ///
+    /// A separator is part of the key if it is quoted or escaped, e.g.
+    /// 'a:b'=c or a\:b=c both send {"a:b":"c"}. A quote only starts quoting
+    /// at the beginning of the key, so Don't:x needs no escaping, and `\`
+    /// only escapes separators and quotes. The value is used as is.
     #[structopt(parse(try_from_str = parse_param))]
     pub parameters: Vec<Parameter>,
 }
@@ -131,9 +358,9 @@ impl App {
         Ok(())
     }
 
-    pub fn process_config_file(&mut self) {
+    pub fn process_config_file(&mut self) -> HurlResult<()> {
         let config_path = config::config_file(self);
-        let config_opt = config::read_config_file(config_path);
+        let config_opt = config::read_config_file(config_path.clone())?;
         if let Some(mut config) = config_opt {
             if self.verbose == 0 {
                 if let Some(v) = config.verbose {
@@ -150,13 +377,73 @@ impl App {
                     self.secure = s;
                 }
             }
+            if let Some(ignore) = config.ignore.take() {
+                self.ignore.extend(ignore);
+            }
+            if !self.cache {
+                if let Some(c) = config.cache {
+                    self.cache = c;
+                }
+            }
+            if config.history == Some(false) {
+                self.no_history = true;
+            }
             if self.auth.is_none() {
                 self.auth = config.auth.take();
             }
             if self.token.is_none() {
                 self.token = config.token.take();
             }
+            if let Some(name) = &self.profile {
+                self.profile_config = config
+                    .profiles
+                    .remove(name)
+                    .ok_or_else(|| Error::UnknownProfile(name.clone()))?;
+            }
+            if let Some(path) = &self.profile_config.openapi {
+                let path = match config_path.parent() {
+                    Some(dir) => dir.join(path),
+                    None => path.clone(),
+                };
+                debug!("Loading OpenAPI spec from {}", path.display());
+                self.openapi = Some(openapi::Spec::load(&path)?);
+            }
+        } else if let Some(name) = &self.profile {
+            return Err(Error::UnknownProfile(name.clone()));
+        }
+        Ok(())
+    }
+
+    /// Reads the document given with --schema, so that a bad schema is reported before the
+    /// request is sent.
+    pub fn load_schema(&mut self) -> HurlResult<()> {
+        if let Some(path) = &self.schema {
+            debug!("Loading JSON Schema from {}", path.display());
+            self.response_schema = Some(schema::load(path)?);
         }
+        Ok(())
+    }
+
+    /// Takes the settings a recorded request was sent with, unless they were given again.
+    pub fn replay_from(&mut self, entry: &history::Entry) {
+        if self.session.is_none() {
+            self.session = entry.session.clone();
+        }
+        if self.profile.is_none() {
+            self.profile = entry.profile.clone();
+        }
+        self.form |= entry.form;
+        self.secure |= entry.secure;
+        if self.unix_socket.is_none() {
+            self.unix_socket = entry.unix_socket.clone();
+        }
+        if self.resolve.is_empty() {
+            self.resolve = entry.resolve.clone();
+        }
+        if self.compress.is_none() {
+            self.compress = entry.compress;
+        }
+        self.raw |= entry.raw;
     }
 
     pub fn log_level(&self) -> Option<&'static str> {
@@ -176,14 +463,96 @@ impl App {
     pub fn host(&self) -> String {
         if let Some(url) = &self.url {
             make_safe_pathname(url)
-        } else if let Some(cmd) = &self.cmd {
-            make_safe_pathname(&cmd.data().url)
+        } else if let Some(url) = self.cmd.as_ref().and_then(Command::url) {
+            make_safe_pathname(url)
         } else {
-            unreachable!()
+            String::new()
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    #[structopt(flatten)]
+    Method(Method),
+    /// Issue two requests and show the differences between the responses.
+    ///
+    /// The bodies are compared structurally if they are JSON, e.g. to
+    /// compare staging and production: hurl diff staging.example.com/api
+    /// example.com/api. Keys given with --ignore are skipped.
+    Diff(DiffData),
+    /// List the operations in the OpenAPI spec of the profile.
+    ///
+    /// Each operation is shown with its method, path, operationId and
+    /// summary, e.g. hurl --profile petstore ops pet.
+    Ops(OpsData),
+    /// List recent requests with their status and latency.
+    ///
+    /// Every request given on the command line is recorded in history.jsonl
+    /// in the configuration directory along with its parameters, session,
+    /// profile and transport options. With --watch only the first poll is
+    /// recorded, and diff is not recorded. The file can only be read by its
+    /// owner. The most recent 1000 are kept.
+    History(HistoryData),
+    /// Send a request from the history again.
+    ///
+    /// The request is sent with its original method, URL and parameters,
+    /// and with its session, profile, --unix-socket, --resolve, --compress
+    /// and --raw unless others are given.
+    Replay(ReplayData),
+}
+
+impl Command {
+    /// The URL of the (first) request made by this command, if it makes one.
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            Command::Method(method) => Some(&method.data().url),
+            Command::Diff(data) => Some(&data.url_a),
+            Command::Ops(_) | Command::History(_) | Command::Replay(_) => None,
         }
     }
 }
 
+#[derive(StructOpt, Debug)]
+pub struct HistoryData {
+    /// How many requests to list.
+    #[structopt(short = "n", long, default_value = "20")]
+    pub count: usize,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ReplayData {
+    /// The id of the request, as shown by `hurl history`.
+    pub id: u64,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct OpsData {
+    /// Only list operations whose path, operationId or summary contains
+    /// this text, ignoring case.
+    pub filter: Option<String>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct DiffData {
+    /// The URL of the first request.
+    pub url_a: String,
+
+    /// The URL of the second request.
+    pub url_b: String,
+
+    /// The HTTP method to use for both requests.
+    #[structopt(short = "X", long, default_value = "GET")]
+    pub method: reqwest::Method,
+
+    /// The headers, data, and query parameters to add to both requests.
+    ///
+    /// These take the same form as the parameters of a method command,
+    /// see `hurl GET --help`.
+    #[structopt(parse(try_from_str = parse_param))]
+    pub parameters: Vec<Parameter>,
+}
+
 #[derive(StructOpt, Debug)]
 #[structopt(rename_all = "screaming_snake_case")]
 pub enum Method {
@@ -239,7 +608,11 @@ pub struct MethodData {
     ///
     /// File upload -- key@filename
     ///
-    ///   this simulates a file upload via multipart/form-data nd requires --form
+    ///   this simulates a file upload via multipart/form-data. Use - as the
+    ///   filename to read from stdin. Options may follow the filename, e.g.
+    ///   photo@me.png;type=image/png;filename=avatar.png sets the MIME type
+    ///   and the file name sent to the server. Other parameters with data
+    ///   become parts of the same body, with raw JSON sent as application/json.
     ///
     /// Query parameter -- key==value
     ///
@@ -260,11 +633,17 @@ pub struct MethodData {
     /// Raw JSON data from file -- key:=@filename
     ///
     ///   e.g. foo:=@bar.json becomes {"foo":{"bar":"this is from bar.json"}}
+    ///
+    /// A separator is part of the key if it is quoted or escaped, e.g.
+    /// 'a:b'=c or a\:b=c both send {"a:b":"c"}. A quote only starts quoting
+    /// at the beginning of the key, so Don't:x needs no escaping, and `\`
+    /// only escapes separators and quotes. The value is used as is.
     #[structopt(parse(try_from_str = parse_param))]
     pub parameters: Vec<Parameter>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Parameter {
     // :
     Header { key: String, value: String },
@@ -299,133 +678,88 @@ impl Parameter {
     }
 }
 
-#[derive(Debug)]
-enum Token<'a> {
-    Text(&'a str),
-    Escape(char),
-}
-
-/// This scans the `src` argument by looking for any escaped `@`, `\`, `=`, and `:` characters
-/// (which would be preceeded by a `\`) while tokenizing the remaining characters.
-fn gather_escapes<'a>(src: &'a str) -> Vec<Token<'a>> {
-    let mut tokens = Vec::new();
-    let mut start = 0;
-    let mut end = 0;
-    let mut chars = src.chars();
-    loop {
-        let a = chars.next();
-        if a.is_none() {
-            if start != end {
-                tokens.push(Token::Text(&src[start..end]))
-            }
-            return tokens;
-        }
-        let c = a.unwrap();
-        if c != '\\' {
-            end += 1;
-            continue;
-        }
-        let b = chars.next();
-        if b.is_none() {
-            tokens.push(Token::Text(&src[start..end + 1]));
-            return tokens;
-        }
-        let c = b.unwrap();
-        match c {
-            '\\' | '=' | '@' | ':' => {
-                if start != end {
-                    tokens.push(Token::Text(&src[start..end]));
-                }
-                tokens.push(Token::Escape(c));
-                end += 2;
-                start = end;
-            }
-            _ => end += 2,
-        }
-    }
-}
-
+/// Parses a parameter of the form `key<separator>value`.
+///
+/// The key ends at the first separator which is neither escaped nor quoted. A `\` before a
+/// separator character or a quote makes it literal, and is itself literal before anything else.
+/// A key which starts with a quote is quoted up to the matching quote: text in single quotes is
+/// literal, and text in double quotes is literal except for `\"` and `\\`. Quotes anywhere else
+/// are part of the key. The value is everything after the separator, taken as is, since it may
+/// itself be JSON or a path.
 fn parse_param(src: &str) -> HurlResult<Parameter> {
     debug!("Parsing: {}", src);
     let separators = [":=@", "=@", "==", ":=", "@", "=", ":"];
-    let tokens = gather_escapes(src);
-
-    let mut found = Vec::new();
-    // Index where the separator starts.
-    let mut idx = 0;
-    for (i, token) in tokens.iter().enumerate() {
-        match token {
-            Token::Text(s) => {
-                for sep in separators.iter() {
-                    if let Some(n) = s.find(sep) {
-                        found.push((n, sep));
-                    }
-                }
-                if !found.is_empty() {
-                    idx = i;
-                    // break since we found a separator.
-                    break;
-                }
-            }
-            Token::Escape(_) => {}
-        }
-    }
-    if found.is_empty() {
-        return Err(Error::ParameterMissingSeparator(src.to_owned()));
-    }
-    // Sort the found separator tokens so the first element is the earliest index and longest
-    // separator.
-    found.sort_by(|(ai, asep), (bi, bsep)| ai.cmp(bi).then(bsep.len().cmp(&asep.len())));
-    let sep = found.first().unwrap().1;
-    trace!("Found separator: {}", sep);
+    let syntax_error = |offset: usize, message: String| Error::ParameterSyntax {
+        param: src.to_owned(),
+        offset,
+        message,
+    };
 
     let mut key = String::new();
-    let mut value = String::new();
-    for (i, token) in tokens.iter().enumerate() {
-        if i < idx {
-            match token {
-                Token::Text(s) => key.push_str(&s),
-                Token::Escape(c) => {
-                    key.push('\\');
-                    key.push(*c);
+    // The open quote character and where it is.
+    let mut quote: Option<(char, usize)> = match src.chars().next() {
+        Some(c) if c == '\'' || c == '"' => Some((c, 0)),
+        _ => None,
+    };
+    let mut chars = src.char_indices().skip(quote.map_or(0, |_| 1));
+    while let Some((offset, c)) = chars.next() {
+        match quote {
+            Some((q, _)) if c == q => quote = None,
+            Some(('"', _)) if c == '\\' => match chars.next() {
+                Some((_, e)) if e == '"' || e == '\\' => key.push(e),
+                Some((_, e)) => {
+                    key.push(c);
+                    key.push(e);
                 }
-            }
-        } else if i > idx {
-            match token {
-                Token::Text(s) => value.push_str(&s),
-                Token::Escape(c) => {
-                    value.push('\\');
-                    value.push(*c);
+                None => break,
+            },
+            Some(_) => key.push(c),
+            None if c == '\\' => match chars.next() {
+                Some((_, e)) if ESCAPABLE.contains(&e) => key.push(e),
+                Some((_, e)) => {
+                    key.push(c);
+                    key.push(e);
                 }
-            }
-        } else {
-            if let Token::Text(s) = token {
-                let parts: Vec<&str> = s.splitn(2, sep).collect();
-                let k = parts.first().unwrap();
-                let v = parts.last().unwrap();
-                key.push_str(k);
-                value.push_str(v);
-            } else {
-                unreachable!();
-            }
+                None => key.push(c),
+            },
+            None => match separators
+                .iter()
+                .find(|sep| src[offset..].starts_with(*sep))
+            {
+                Some(sep) => {
+                    trace!("Found separator: {}", sep);
+                    if key.is_empty() {
+                        return Err(syntax_error(
+                            offset,
+                            format!("missing key before `{}`", sep),
+                        ));
+                    }
+                    let value = src[offset + sep.len()..].to_owned();
+                    return Ok(match Separator::try_from(*sep) {
+                        Ok(Separator::At) => Parameter::FormFile { key, value },
+                        Ok(Separator::Equal) => Parameter::Data { key, value },
+                        Ok(Separator::Colon) => Parameter::Header { key, value },
+                        Ok(Separator::ColonEqual) => Parameter::RawJsonData { key, value },
+                        Ok(Separator::EqualEqual) => Parameter::Query { key, value },
+                        Ok(Separator::EqualAt) => Parameter::DataFile { key, value },
+                        Ok(Separator::Snail) => Parameter::RawJsonDataFile { key, value },
+                        Err(()) => unreachable!(),
+                    });
+                }
+                None => key.push(c),
+            },
         }
     }
 
-    if let Ok(separator) = Separator::try_from(*sep) {
-        match separator {
-            Separator::At => Ok(Parameter::FormFile { key, value }),
-            Separator::Equal => Ok(Parameter::Data { key, value }),
-            Separator::Colon => Ok(Parameter::Header { key, value }),
-            Separator::ColonEqual => Ok(Parameter::RawJsonData { key, value }),
-            Separator::EqualEqual => Ok(Parameter::Query { key, value }),
-            Separator::EqualAt => Ok(Parameter::DataFile { key, value }),
-            Separator::Snail => Ok(Parameter::RawJsonDataFile { key, value }),
-        }
-    } else {
-        unreachable!();
+    match quote {
+        Some((q, offset)) => Err(syntax_error(offset, format!("unterminated {} quote", q))),
+        None => Err(Error::ParameterMissingSeparator(src.to_owned())),
     }
 }
 
+/// The characters a `\` escapes outside of quotes: those separators are made of, and quotes.
+const ESCAPABLE: [char; 5] = [':', '=', '@', '\'', '"'];
+
 #[derive(Debug)]
 enum Separator {
     Colon,
@@ -453,3 +787,113 @@ impl TryFrom<&str> for Separator {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(src: &str) -> (String, String) {
+        match parse_param(src).unwrap() {
+            Parameter::Header { key, value }
+            | Parameter::Data { key, value }
+            | Parameter::Query { key, value }
+            | Parameter::RawJsonData { key, value }
+            | Parameter::FormFile { key, value }
+            | Parameter::DataFile { key, value }
+            | Parameter::RawJsonDataFile { key, value } => (key, value),
+        }
+    }
+
+    fn syntax_error_offset(src: &str) -> usize {
+        match parse_param(src) {
+            Err(Error::ParameterSyntax { offset, .. }) => offset,
+            other => panic!("expected a syntax error for {}, got {:?}", src, other),
+        }
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_owned(), value.to_owned())
+    }
+
+    #[test]
+    fn multibyte_keys_and_values() {
+        assert_eq!(parse("héllo:wörld"), pair("héllo", "wörld"));
+        assert_eq!(parse("ключ==значение"), pair("ключ", "значение"));
+        assert_eq!(parse("日本:=\"語\""), pair("日本", "\"語\""));
+        assert_eq!(parse("é\\:ü=1"), pair("é:ü", "1"));
+        assert_eq!(parse("'ü:é'=ß"), pair("ü:é", "ß"));
+    }
+
+    #[test]
+    fn separators_pick_the_longest_match() {
+        assert!(matches!(
+            parse_param("a:=@b").unwrap(),
+            Parameter::RawJsonDataFile { .. }
+        ));
+        assert!(matches!(
+            parse_param("a=@b").unwrap(),
+            Parameter::DataFile { .. }
+        ));
+        assert!(matches!(
+            parse_param("a==b").unwrap(),
+            Parameter::Query { .. }
+        ));
+        assert!(matches!(
+            parse_param("a:=1").unwrap(),
+            Parameter::RawJsonData { .. }
+        ));
+        assert!(matches!(
+            parse_param("a@b").unwrap(),
+            Parameter::FormFile { .. }
+        ));
+        assert!(matches!(
+            parse_param("a=b").unwrap(),
+            Parameter::Data { .. }
+        ));
+        assert!(matches!(
+            parse_param("a:b").unwrap(),
+            Parameter::Header { .. }
+        ));
+    }
+
+    #[test]
+    fn value_is_taken_as_is() {
+        assert_eq!(parse("a=b:c='d'\\e"), pair("a", "b:c='d'\\e"));
+    }
+
+    #[test]
+    fn quoted_keys() {
+        assert_eq!(parse("'a:b'=c"), pair("a:b", "c"));
+        assert_eq!(parse("'a\\'=c"), pair("a\\", "c"));
+        assert_eq!(parse("\"a\\\"b:c\":d"), pair("a\"b:c", "d"));
+        assert_eq!(parse("\"a\\\\\"=b"), pair("a\\", "b"));
+        assert_eq!(parse("\"a\\nb\"=c"), pair("a\\nb", "c"));
+    }
+
+    #[test]
+    fn quotes_inside_a_key_are_literal() {
+        assert_eq!(parse("Don't:x"), pair("Don't", "x"));
+        assert_eq!(parse("say\"hi\"=1"), pair("say\"hi\"", "1"));
+        assert_eq!(parse("'a'b'=c"), pair("ab'", "c"));
+    }
+
+    #[test]
+    fn escapes_only_separators_and_quotes() {
+        assert_eq!(parse("a\\:b=c"), pair("a:b", "c"));
+        assert_eq!(parse("a\\=\\@b:c"), pair("a=@b", "c"));
+        assert_eq!(parse("\\'a=b"), pair("'a", "b"));
+        assert_eq!(parse("a\\b:c"), pair("a\\b", "c"));
+        assert_eq!(parse("a\\\\:c"), pair("a\\\\", "c"));
+    }
+
+    #[test]
+    fn syntax_errors_point_at_the_problem() {
+        assert_eq!(syntax_error_offset(":x"), 0);
+        assert_eq!(syntax_error_offset("'':x"), 2);
+        assert_eq!(syntax_error_offset("'ü:x"), 0);
+        assert!(matches!(
+            parse_param("ü\\"),
+            Err(Error::ParameterMissingSeparator(_))
+        ));
+    }
+}
@@ -0,0 +1,77 @@
+use log::trace;
+use reqwest::header::{
+    HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::{Method, Response, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+
+/// A response body stored in a session along with the validators the server sent for it.
+///
+/// The validators are sent back as `If-None-Match` and `If-Modified-Since` on the next request
+/// for the same URL so that the server can answer with `304 Not Modified` instead of the body.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CacheEntry {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: String,
+}
+
+impl CacheEntry {
+    /// Builds an entry for a successful response which carries at least one validator.
+    pub fn from_response(resp: &Response, body: &str) -> Option<CacheEntry> {
+        if resp.status() != StatusCode::OK {
+            return None;
+        }
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v: &HeaderValue| v.to_str().ok())
+                .map(|v| v.to_owned())
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        if etag.is_none() && last_modified.is_none() {
+            return None;
+        }
+        Some(CacheEntry {
+            etag,
+            last_modified,
+            body: body.to_owned(),
+        })
+    }
+
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// Adds the conditional headers for this entry, unless the request already has its own.
+    pub fn add_conditional_headers(&self, headers: &mut HeaderMap) {
+        if headers.contains_key(IF_NONE_MATCH) || headers.contains_key(IF_MODIFIED_SINCE) {
+            trace!("Request has conditional headers, not adding cached validators");
+            return;
+        }
+        if let Some(etag) = self
+            .etag
+            .as_ref()
+            .and_then(|v| HeaderValue::from_str(v).ok())
+        {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        if let Some(date) = self
+            .last_modified
+            .as_ref()
+            .and_then(|v| HeaderValue::from_str(v).ok())
+        {
+            headers.insert(IF_MODIFIED_SINCE, date);
+        }
+    }
+}
+
+/// Only GET requests are cached, and they are cached by their full URL including the query.
+pub fn key(method: &Method, url: &Url) -> Option<String> {
+    if method == Method::GET {
+        Some(url.to_string())
+    } else {
+        None
+    }
+}
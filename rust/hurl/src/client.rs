@@ -1,23 +1,57 @@
 use crate::app::{App, Method, Parameter};
-use crate::errors::{Error, HurlResult};
+use crate::cache;
+use crate::credentials;
+use crate::encoding;
+use crate::errors::{Access, Error, HurlResult};
+use crate::history;
+use crate::multipart;
+use crate::retry::RetryPolicy;
 use crate::session::Session;
-use log::{self, debug, info, log_enabled, trace};
+use crate::sign;
+use crate::transport;
+use log::{self, debug, info, log_enabled, trace, warn};
+use reqwest::header::{HeaderValue, ACCEPT_ENCODING, AUTHORIZATION, CONTENT_TYPE};
 use reqwest::multipart::Form;
-use reqwest::{Client, RequestBuilder, Response, Url};
+use reqwest::{Client, Request, RequestBuilder, Response, Url};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufRead;
-use std::time::Instant;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The body built from the data parameters.
+///
+/// JSON and form bodies are kept as bytes until the request is built so that later stages, such
+/// as signing, see exactly what will be sent. Multipart bodies are streamed by reqwest.
+pub enum Payload {
+    Empty,
+    Bytes {
+        content_type: &'static str,
+        bytes: Vec<u8>,
+    },
+    Streamed,
+}
+
+/// The response to a request along with how many attempts it took to get it.
+pub struct Exchange {
+    pub method: reqwest::Method,
+    pub url: Url,
+    pub response: Response,
+    pub attempts: u32,
+}
 
 pub fn perform_method(
     app: &App,
+    client: &Client,
     method: &Method,
     session: &mut Option<Session>,
-) -> HurlResult<Response> {
+) -> HurlResult<Exchange> {
     let method_data = method.data();
-    perform(
+    perform_recorded(
         app,
+        client,
         method.into(),
         session,
         &method_data.url,
@@ -25,27 +59,71 @@ pub fn perform_method(
     )
 }
 
+/// Sends a request without recording it in the history, for commands which send several.
 pub fn perform(
     app: &App,
+    client: &Client,
+    method: reqwest::Method,
+    session: &mut Option<Session>,
+    raw_url: &str,
+    parameters: &Vec<Parameter>,
+) -> HurlResult<Exchange> {
+    send_request(app, client, method, session, raw_url, parameters, false)
+}
+
+/// Sends the request a command was given and records it in the history, unless --no-history.
+pub fn perform_recorded(
+    app: &App,
+    client: &Client,
+    method: reqwest::Method,
+    session: &mut Option<Session>,
+    raw_url: &str,
+    parameters: &Vec<Parameter>,
+) -> HurlResult<Exchange> {
+    send_request(
+        app,
+        client,
+        method,
+        session,
+        raw_url,
+        parameters,
+        !app.no_history,
+    )
+}
+
+fn send_request(
+    app: &App,
+    client: &Client,
     method: reqwest::Method,
     session: &mut Option<Session>,
     raw_url: &str,
     parameters: &Vec<Parameter>,
-) -> HurlResult<Response> {
-    let client = Client::new();
-    let url = parse(app, raw_url)?;
+    record: bool,
+) -> HurlResult<Exchange> {
+    let url = parse(app, raw_url).map_err(|source| Error::UrlParseError {
+        url: raw_url.to_owned(),
+        source,
+    })?;
     debug!("Parsed url: {}", url);
+    let resolve = transport::find(&app.resolve, &url);
+    if app.http2 && url.scheme() == "http" && app.unix_socket.is_none() {
+        warn!("Upgrading to h2c is not supported, sending HTTP/1.1; try --http2-prior-knowledge");
+    }
+    // An https request keeps its URL so that the certificate is checked against the host, and
+    // reaches the overriding address through the client's tunnel.
+    let tunnel = resolve.is_some() && url.scheme() == "https" && app.unix_socket.is_none();
 
     let is_multipart = parameters.iter().any(|p| p.is_form_file());
     if is_multipart {
         trace!("Making multipart request because form file was given");
-        if !app.form {
-            return Err(Error::NotFormButHasFormFile);
-        }
     }
 
-    let mut builder = client.request(method, url);
-    builder = handle_session(
+    // Checked before the session takes this request's headers, so that only a header it already
+    // had counts.
+    let has_authorization = parameters.iter().any(is_authorization)
+        || session.iter().any(|s| s.has_header(AUTHORIZATION.as_str()));
+    let builder = client.request(method.clone(), url.clone());
+    let builder = handle_session(
         builder,
         session,
         parameters,
@@ -53,37 +131,226 @@ pub fn perform(
         &app.auth,
         &app.token,
     );
-    builder = handle_parameters(builder, app.form, is_multipart, parameters)?;
-    builder = handle_auth(builder, &app.auth, &app.token)?;
+    let (builder, payload) = handle_parameters(builder, app.form, is_multipart, parameters)?;
+    let builder = handle_auth(builder, app, &url, has_authorization)?;
 
+    let mut request = builder.build()?;
+    attach_payload(&mut request, &payload);
+    if !app.no_validate {
+        if let Some(spec) = &app.openapi {
+            spec.check_request(&request, &payload)?;
+        }
+    }
+    if app.cache {
+        handle_cache(&mut request, session);
+    }
+    let payload = match app.compress {
+        Some(encoding) => encoding::compress(&mut request, payload, encoding)?,
+        None => payload,
+    };
+    if app.raw && !request.headers().contains_key(ACCEPT_ENCODING) {
+        request.headers_mut().insert(
+            ACCEPT_ENCODING,
+            HeaderValue::from_static(encoding::ACCEPT_ALL),
+        );
+    }
+    handle_signing(&mut request, &payload, app)?;
+    // The session and cache know the request by the URL it was given, query included, not the
+    // address it was resolved to.
+    let logical_url = request.url().clone();
+    if let Some(resolve) = resolve.filter(|_| !tunnel) {
+        resolve.apply(&mut request)?;
+    }
+
+    let started = Instant::now();
+    let result = match &app.unix_socket {
+        Some(path) => {
+            if app.retry > 0 {
+                warn!("Retries are not supported over a Unix socket, ignoring --retry");
+            }
+            if app.http2 || app.http2_prior_knowledge {
+                warn!("Only HTTP/1.1 is spoken over a Unix socket");
+            }
+            trace!("Sending request over {}", path.display());
+            transport::send_unix(path, &request, &payload).map(|response| Exchange {
+                method: method.clone(),
+                url: url.clone(),
+                response,
+                attempts: 1,
+            })
+        }
+        None => send_with_retries(client, request, &RetryPolicy::from_app(app)),
+    };
+    if record {
+        handle_history(app, method, raw_url, parameters, &result, started.elapsed());
+    }
+
+    let mut exchange = result?;
+    exchange.url = logical_url;
+    Ok(exchange)
+}
+
+fn handle_history(
+    app: &App,
+    method: reqwest::Method,
+    raw_url: &str,
+    parameters: &[Parameter],
+    result: &HurlResult<Exchange>,
+    elapsed: Duration,
+) {
+    let mut entry = history::Entry::new(app, method, raw_url, parameters, elapsed);
+    match result {
+        Ok(exchange) => entry.status = Some(exchange.response.status().as_u16()),
+        Err(e) => entry.error = Some(e.to_string()),
+    }
+    if let Err(e) = history::record(entry) {
+        warn!("Could not record the request in the history: {}", e);
+    }
+}
+
+/// Builds the client every request of this run is sent with, so that a --resolve tunnel is
+/// only started once and stops when the client is dropped.
+pub fn build_client(app: &App) -> HurlResult<Client> {
+    let mut builder = Client::builder();
+    if !app.resolve.is_empty() && app.unix_socket.is_none() {
+        builder = builder.proxy(transport::tunnel(&app.resolve)?);
+    }
+    if app.raw {
+        builder = builder.gzip(false);
+    }
+    // The default TLS backend never offers ALPN, so it only speaks HTTP/1.1 over https and
+    // HTTP/2 needs rustls. Both check the certificate against the host of the URL, and a
+    // --resolve override reaches them through the same tunnel, so the choice of backend does not
+    // change how an overridden host is verified.
+    if app.http1_1 {
+        builder = builder.use_default_tls();
+    } else if app.http2 || app.http2_prior_knowledge {
+        builder = builder.use_rustls_tls();
+    }
+    if app.http2_prior_knowledge {
+        builder = builder.h2_prior_knowledge();
+    }
+    Ok(builder.build()?)
+}
+
+fn send_with_retries(
+    client: &Client,
+    mut request: Request,
+    policy: &RetryPolicy,
+) -> HurlResult<Exchange> {
+    let method = request.method().clone();
+    let url = request.url().clone();
+    let max_attempts = policy.retries() + 1;
+    let mut attempt = 1;
+    loop {
+        // The request is consumed by sending it, so we hold on to a copy for the next attempt.
+        // Bodies which are streamed, e.g. multipart uploads, cannot be copied.
+        let next = if attempt < max_attempts {
+            request.try_clone()
+        } else {
+            None
+        };
+        if attempt == 1 && max_attempts > 1 && next.is_none() {
+            warn!("Request body cannot be replayed, so retries are disabled");
+        }
+
+        info!("Attempt {} of {}", attempt, max_attempts);
+        let result = send(client, request);
+        let delay = match (&result, &next) {
+            (Ok(resp), Some(_)) if policy.should_retry_response(resp) => {
+                info!("Attempt {} failed with status {}", attempt, resp.status());
+                policy.delay(attempt, Some(resp))
+            }
+            (Err(e), Some(_)) if policy.should_retry_error(e) => {
+                info!("Attempt {} failed: {}", attempt, e);
+                policy.delay(attempt, None)
+            }
+            _ => {
+                return result
+                    .map(|response| Exchange {
+                        method,
+                        url,
+                        response,
+                        attempts: attempt,
+                    })
+                    .map_err(From::from)
+            }
+        };
+        info!("Retrying in {:?}", delay);
+        thread::sleep(delay);
+        request = next.unwrap();
+        attempt += 1;
+    }
+}
+
+fn send(client: &Client, request: Request) -> reqwest::Result<Response> {
     if log_enabled!(log::Level::Info) {
         let start = Instant::now();
-        let result = builder.send().map_err(From::from);
+        let result = client.execute(request);
         let elapsed = start.elapsed();
         info!("Elapsed time: {:?}", elapsed);
         result
     } else {
-        builder.send().map_err(From::from)
+        client.execute(request)
+    }
+}
+
+fn handle_cache(request: &mut Request, session: &Option<Session>) {
+    let s = match session {
+        Some(s) => s,
+        None => {
+            warn!("Caching requires a session, ignoring --cache");
+            return;
+        }
+    };
+    let entry = cache::key(request.method(), request.url()).and_then(|key| s.cached(&key));
+    if let Some(entry) = entry {
+        trace!("Adding conditional headers from cached response");
+        entry.add_conditional_headers(request.headers_mut());
     }
 }
 
+/// Adds the credentials given with --auth or --token. Without them, the credential helper or
+/// .netrc is asked, unless the request already carries an Authorization header.
 fn handle_auth(
     mut builder: RequestBuilder,
-    auth: &Option<String>,
-    token: &Option<String>,
+    app: &App,
+    url: &Url,
+    has_authorization: bool,
 ) -> HurlResult<RequestBuilder> {
-    if let Some(auth_string) = auth {
-        let (username, maybe_password) = parse_auth(&auth_string)?;
+    let host = url.host_str().unwrap_or("");
+    if let Some(auth_string) = &app.auth {
+        let (username, maybe_password) = parse_auth(&auth_string, host)?;
         trace!("Parsed basic authentication. Username={}", username);
         builder = builder.basic_auth(username, maybe_password);
     }
-    if let Some(bearer) = token {
+    if let Some(bearer) = &app.token {
         trace!("Parsed bearer authentication. Token={}", bearer);
         builder = builder.bearer_auth(bearer);
     }
+    if app.auth.is_some() || app.token.is_some() || has_authorization {
+        return Ok(builder);
+    }
+    if let Some(command) = &app.profile_config.credential_helper {
+        let bearer = credentials::run_helper(command, url)?;
+        trace!("Using bearer token from the credential helper");
+        builder = builder.bearer_auth(bearer);
+    } else if let Some(entry) = credentials::netrc_lookup(host) {
+        if let Some(login) = entry.login {
+            trace!("Using basic authentication from .netrc. Username={}", login);
+            builder = builder.basic_auth(login, entry.password);
+        }
+    }
     Ok(builder)
 }
 
+fn is_authorization(parameter: &Parameter) -> bool {
+    match parameter {
+        Parameter::Header { key, .. } => key.eq_ignore_ascii_case(AUTHORIZATION.as_str()),
+        _ => false,
+    }
+}
+
 fn handle_session(
     mut builder: RequestBuilder,
     session: &mut Option<Session>,
@@ -109,7 +376,7 @@ fn handle_parameters(
     is_form: bool,
     is_multipart: bool,
     parameters: &Vec<Parameter>,
-) -> HurlResult<RequestBuilder> {
+) -> HurlResult<(RequestBuilder, Payload)> {
     let mut data: HashMap<&String, Value> = HashMap::new();
     let mut multipart = if is_multipart {
         Some(Form::new())
@@ -137,40 +404,105 @@ fn handle_parameters(
             }
             Parameter::RawJsonData { key, value } => {
                 trace!("Adding JSON data: {}", key);
-                let v: Value = serde_json::from_str(value)?;
-                data.insert(key, v);
+                let v: Value =
+                    serde_json::from_str(value).map_err(|source| Error::ParameterJson {
+                        key: key.to_owned(),
+                        value: value.to_owned(),
+                        source,
+                    })?;
+                if let Some(m) = multipart.take() {
+                    multipart = Some(m.part(key.to_owned(), multipart::json_part(key, &v)?));
+                } else {
+                    data.insert(key, v);
+                }
             }
             Parameter::RawJsonDataFile { key, value } => {
                 trace!("Adding JSON data for key={} from file={}", key, value);
-                let file = File::open(value)?;
-                let v: Value = serde_json::from_reader(file)?;
-                data.insert(key, v);
+                let path = PathBuf::from(value);
+                let file = match File::open(&path) {
+                    Ok(file) => file,
+                    Err(source) => {
+                        return Err(Error::File {
+                            path,
+                            access: Access::Read,
+                            source,
+                        })
+                    }
+                };
+                let v: Value = serde_json::from_reader(file).map_err(|source| {
+                    Error::ParameterJsonFile {
+                        key: key.to_owned(),
+                        path,
+                        source,
+                    }
+                })?;
+                if let Some(m) = multipart.take() {
+                    multipart = Some(m.part(key.to_owned(), multipart::json_part(key, &v)?));
+                } else {
+                    data.insert(key, v);
+                }
             }
             Parameter::DataFile { key, value } => {
                 trace!("Adding data from file={} for key={}", value, key);
-                let value = std::fs::read_to_string(value)?;
-                data.insert(key, Value::String(value));
+                let value = std::fs::read_to_string(value).map_err(|source| Error::File {
+                    path: PathBuf::from(value),
+                    access: Access::Read,
+                    source,
+                })?;
+                if multipart.is_none() {
+                    data.insert(key, Value::String(value));
+                } else {
+                    multipart = multipart.map(|m| m.text(key.to_owned(), value));
+                }
             }
             Parameter::FormFile { key, value } => {
-                trace!("Adding file={} with key={}", value, key);
-                multipart = Some(multipart.unwrap().file(key.to_owned(), value.to_owned())?);
+                let spec = multipart::FileSpec::parse(value);
+                trace!("Adding file={} with key={}", spec.path, key);
+                let part = spec.into_part(key)?;
+                multipart = multipart.map(|m| m.part(key.to_owned(), part));
             }
         }
     }
 
-    if let Some(m) = multipart {
+    let payload = if let Some(m) = multipart {
         builder = builder.multipart(m);
+        Payload::Streamed
+    } else if data.is_empty() {
+        Payload::Empty
+    } else if is_form {
+        Payload::Bytes {
+            content_type: "application/x-www-form-urlencoded",
+            bytes: serde_urlencoded::to_string(&data)?.into_bytes(),
+        }
     } else {
-        if !data.is_empty() {
-            if is_form {
-                builder = builder.form(&data);
-            } else {
-                builder = builder.json(&data);
-            }
+        Payload::Bytes {
+            content_type: "application/json",
+            bytes: serde_json::to_vec(&data)?,
         }
+    };
+
+    Ok((builder, payload))
+}
+
+fn attach_payload(request: &mut Request, payload: &Payload) {
+    if let Payload::Bytes {
+        content_type,
+        bytes,
+    } = payload
+    {
+        request
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        *request.body_mut() = Some(bytes.clone().into());
     }
+}
 
-    Ok(builder)
+fn handle_signing(request: &mut Request, payload: &Payload, app: &App) -> HurlResult<()> {
+    if let Some(config) = &app.profile_config.signer {
+        trace!("Signing request");
+        sign::from_config(config).sign(request, payload)?;
+    }
+    Ok(())
 }
 
 fn parse(app: &App, s: &str) -> Result<Url, reqwest::UrlError> {
@@ -191,7 +523,7 @@ fn parse(app: &App, s: &str) -> Result<Url, reqwest::UrlError> {
     }
 }
 
-fn parse_auth(s: &str) -> HurlResult<(String, Option<String>)> {
+fn parse_auth(s: &str, host: &str) -> HurlResult<(String, Option<String>)> {
     if let Some(idx) = s.find(':') {
         // User has entered `Username:`, so we check if password was submitted as well.
         let (username, password_with_colon) = s.split_at(idx);
@@ -202,7 +534,13 @@ fn parse_auth(s: &str) -> HurlResult<(String, Option<String>)> {
             return Ok((username.to_owned(), Some(password.to_owned())));
         }
     } else {
-        // User has entered `Username`, so we prompt for password.
+        // User has entered `Username`, so we look in .netrc or prompt for password.
+        if let Some(password) = credentials::netrc_lookup(host)
+            .filter(|entry| entry.login.as_deref() == Some(s))
+            .and_then(|entry| entry.password)
+        {
+            return Ok((s.to_owned(), Some(password)));
+        }
         let password = rpassword::read_password_from_tty(Some("Password: "))?;
         return Ok((s.to_owned(), Some(password)));
     };
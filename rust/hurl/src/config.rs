@@ -1,9 +1,13 @@
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
+use std::io::ErrorKind;
 use std::path::PathBuf;
 
 use crate::app::App;
 use crate::directories::DIRECTORIES;
+use crate::errors::{Access, Error, HurlResult};
+use crate::sign::SignerConfig;
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
@@ -12,6 +16,22 @@ pub struct Config {
     pub auth: Option<String>,
     pub token: Option<String>,
     pub secure: Option<bool>,
+    pub cache: Option<bool>,
+    pub ignore: Option<Vec<String>>,
+    pub history: Option<bool>,
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+}
+
+/// Settings which only apply when the profile is selected with --profile.
+#[derive(Debug, Default, Deserialize)]
+pub struct Profile {
+    pub signer: Option<SignerConfig>,
+    /// An OpenAPI 3 document, relative to the configuration file, which requests and responses
+    /// are validated against.
+    pub openapi: Option<PathBuf>,
+    /// A shell command which prints a bearer token for the request on stdout.
+    pub credential_helper: Option<String>,
 }
 
 pub fn config_file(app: &App) -> PathBuf {
@@ -22,9 +42,21 @@ pub fn config_file(app: &App) -> PathBuf {
         .unwrap_or_else(|| DIRECTORIES.config().join("config"))
 }
 
-pub fn read_config_file(path: PathBuf) -> Option<Config> {
-    fs::read_to_string(path).ok().map(|content| {
-        let config: Config = toml::from_str(&content).unwrap();
-        config
-    })
-}
\ No newline at end of file
+/// Reads the configuration file at `path`. A missing file is not an error since the
+/// configuration file is optional.
+pub fn read_config_file(path: PathBuf) -> HurlResult<Option<Config>> {
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(source) => {
+            return Err(Error::File {
+                path,
+                access: Access::Read,
+                source,
+            })
+        }
+    };
+    toml::from_str(&content)
+        .map(Some)
+        .map_err(|source| Error::ConfigParse { path, source })
+}
@@ -0,0 +1,198 @@
+use crate::errors::{Error, HurlResult};
+use log::{trace, warn};
+use reqwest::Url;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// A `machine` entry of a `.netrc` file.
+#[derive(Debug, Default)]
+pub struct NetrcEntry {
+    pub machine: String,
+    pub login: Option<String>,
+    pub password: Option<String>,
+}
+
+/// Where the `.netrc` file is: `$NETRC` if it is set, otherwise in the home directory.
+fn netrc_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("NETRC") {
+        return Some(PathBuf::from(path));
+    }
+    let home = dirs::home_dir()?;
+    if cfg!(windows) && !home.join(".netrc").exists() {
+        Some(home.join("_netrc"))
+    } else {
+        Some(home.join(".netrc"))
+    }
+}
+
+/// Finds the `.netrc` entry for `host`.
+///
+/// Only `machine` entries are used. A `default` entry would send its credentials to every host
+/// without one, which is rarely what is wanted from a general purpose client, so it is ignored.
+pub fn netrc_lookup(host: &str) -> Option<NetrcEntry> {
+    let path = netrc_path()?;
+    let content = fs::read_to_string(&path).ok()?;
+    warn_if_readable_by_others(&path);
+    let entry = parse_netrc(&content)
+        .into_iter()
+        .find(|e| e.machine.eq_ignore_ascii_case(host));
+    if entry.is_some() {
+        trace!("Found credentials for {} in {}", host, path.display());
+    }
+    entry
+}
+
+#[cfg(unix)]
+fn warn_if_readable_by_others(path: &std::path::Path) {
+    use std::os::unix::fs::PermissionsExt;
+    if let Ok(metadata) = fs::metadata(path) {
+        if metadata.permissions().mode() & 0o077 != 0 {
+            warn!(
+                "{} can be read by other users, consider chmod 600",
+                path.display()
+            );
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn warn_if_readable_by_others(_path: &std::path::Path) {}
+
+fn parse_netrc(content: &str) -> Vec<NetrcEntry> {
+    let mut entries = Vec::new();
+    let mut current: Option<NetrcEntry> = None;
+    let mut lines = content.lines();
+    let mut tokens: Vec<String> = Vec::new();
+
+    loop {
+        if tokens.is_empty() {
+            match lines.next() {
+                Some(line) => {
+                    tokens = tokenize(line);
+                    tokens.reverse();
+                    continue;
+                }
+                None => break,
+            }
+        }
+        let token = tokens.pop().unwrap();
+        match token.as_str() {
+            "machine" | "default" => {
+                entries.extend(current.take());
+                if token == "machine" {
+                    let machine = next_token(&mut tokens, &mut lines).unwrap_or_default();
+                    current = Some(NetrcEntry {
+                        machine,
+                        ..Default::default()
+                    });
+                }
+            }
+            "login" | "password" | "account" => {
+                let value = next_token(&mut tokens, &mut lines);
+                if let Some(entry) = current.as_mut() {
+                    match token.as_str() {
+                        "login" => entry.login = value,
+                        "password" => entry.password = value,
+                        _ => {}
+                    }
+                }
+            }
+            // A macro definition runs until the next empty line.
+            "macdef" => {
+                tokens.clear();
+                for line in lines.by_ref() {
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+            }
+            other => trace!("Skipping unknown .netrc token {}", other),
+        }
+    }
+    entries.extend(current);
+    entries
+}
+
+/// The next token, which may be on a following line.
+fn next_token<'a>(
+    tokens: &mut Vec<String>,
+    lines: &mut impl Iterator<Item = &'a str>,
+) -> Option<String> {
+    while tokens.is_empty() {
+        *tokens = tokenize(lines.next()?);
+        tokens.reverse();
+    }
+    tokens.pop()
+}
+
+/// Splits a line on whitespace, keeping double quoted tokens together and dropping comments.
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut token = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => token.extend(chars.next()),
+                    c => token.push(c),
+                }
+            }
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+    tokens
+}
+
+/// Runs a credential helper and returns the token it prints.
+///
+/// The command is run by the shell with `HURL_HOST` and `HURL_URL` set to the request being
+/// made, so that one helper can serve several hosts. The first line of its output is the token.
+/// Anything it writes to stderr, such as a login prompt, is passed through.
+pub fn run_helper(command: &str, url: &Url) -> HurlResult<String> {
+    let helper_error = |message: String| Error::CredentialHelper {
+        command: command.to_owned(),
+        message,
+    };
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    trace!("Running credential helper: {}", command);
+    let output = Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .env("HURL_HOST", url.host_str().unwrap_or(""))
+        .env("HURL_URL", url.as_str())
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| helper_error(e.to_string()))?;
+    if !output.status.success() {
+        return Err(helper_error(format!("it exited with {}", output.status)));
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .map_err(|_| helper_error("it printed something which is not UTF-8".to_owned()))?;
+    match stdout.lines().next().map(str::trim) {
+        Some(token) if !token.is_empty() => Ok(token.to_owned()),
+        _ => Err(helper_error("it did not print a token".to_owned())),
+    }
+}
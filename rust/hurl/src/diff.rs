@@ -0,0 +1,255 @@
+use crate::errors::{Access, Error, HurlResult};
+use crate::schema::escape;
+use heck::TitleCase;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+/// Headers which differ on every response, or only because the body does, and are therefore
+/// never compared.
+const ALWAYS_IGNORED_HEADERS: [&str; 2] = ["date", "content-length"];
+
+/// The parts of a response which are compared. This is also the format of baseline files.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub status: u16,
+    /// Every value of each header in the order they were received, as a header such as
+    /// `Set-Cookie` may be sent more than once.
+    pub headers: BTreeMap<String, Vec<String>>,
+    /// The parsed body if it is JSON, otherwise the body as a string.
+    pub body: Value,
+}
+
+impl Snapshot {
+    pub fn from_response(resp: &reqwest::Response, body: &str) -> Snapshot {
+        let mut headers = BTreeMap::new();
+        for (k, v) in resp.headers().iter() {
+            let value = v.to_str().unwrap_or("BAD HEADER VALUE");
+            headers
+                .entry(k.as_str().to_owned())
+                .or_insert_with(Vec::new)
+                .push(value.to_owned());
+        }
+        // `serde_json::Value` keeps object keys in a `BTreeMap`, so the order of keys in the
+        // response does not show up as a difference.
+        let body = serde_json::from_str(body).unwrap_or_else(|_| Value::String(body.to_owned()));
+        Snapshot {
+            status: resp.status().as_u16(),
+            headers,
+            body,
+        }
+    }
+
+    pub fn load(path: &Path) -> HurlResult<Snapshot> {
+        let file = File::open(path).map_err(|source| Error::File {
+            path: path.to_owned(),
+            access: Access::Read,
+            source,
+        })?;
+        serde_json::from_reader(BufReader::new(file)).map_err(|source| Error::BaselineParse {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> HurlResult<()> {
+        let file = File::create(path).map_err(|source| Error::File {
+            path: path.to_owned(),
+            access: Access::Write,
+            source,
+        })?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self).map_err(From::from)
+    }
+}
+
+#[derive(Debug)]
+pub enum Change {
+    Added(Value),
+    Removed(Value),
+    Changed(Value, Value),
+}
+
+/// The differences between two snapshots, keyed by header name or JSON pointer into the body.
+#[derive(Debug, Default)]
+pub struct Diff {
+    pub status: Option<(u16, u16)>,
+    pub headers: Vec<(String, Change)>,
+    pub body: Vec<(String, Change)>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.status.is_none() && self.headers.is_empty() && self.body.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.status.iter().count() + self.headers.len() + self.body.len()
+    }
+
+    pub fn print(&self) {
+        if let Some((a, b)) = self.status {
+            println!("\x1b[33m~ status: {} -> {}\x1b[0m", a, b);
+        }
+        for (name, change) in self.headers.iter() {
+            print_change(&name.to_title_case().replace(' ', "-"), change);
+        }
+        for (pointer, change) in self.body.iter() {
+            print_change(if pointer.is_empty() { "/" } else { pointer }, change);
+        }
+    }
+}
+
+fn print_change(location: &str, change: &Change) {
+    match change {
+        Change::Added(v) => println!("\x1b[32m+ {}: {}\x1b[0m", location, v),
+        Change::Removed(v) => println!("\x1b[31m- {}: {}\x1b[0m", location, v),
+        Change::Changed(a, b) => println!("\x1b[33m~ {}: {} -> {}\x1b[0m", location, a, b),
+    }
+}
+
+/// Compares two snapshots.
+///
+/// Each entry in `ignore` is either a JSON pointer such as `/data/0/id`, which ignores that
+/// value and everything below it, or a plain key such as `request_id`, which ignores the key at
+/// any depth. Plain keys also match header names, case insensitively.
+pub fn diff(a: &Snapshot, b: &Snapshot, ignore: &[String]) -> Diff {
+    let mut result = Diff::default();
+    if a.status != b.status {
+        result.status = Some((a.status, b.status));
+    }
+
+    let ignored_header = |name: &str| {
+        ALWAYS_IGNORED_HEADERS.contains(&name)
+            || ignore.iter().any(|i| i.eq_ignore_ascii_case(name))
+    };
+    for (name, value) in a.headers.iter() {
+        if ignored_header(name) {
+            continue;
+        }
+        match b.headers.get(name) {
+            None => result
+                .headers
+                .push((name.clone(), Change::Removed(header_value(value)))),
+            Some(other) if other != value => result.headers.push((
+                name.clone(),
+                Change::Changed(header_value(value), header_value(other)),
+            )),
+            Some(_) => {}
+        }
+    }
+    for (name, value) in b.headers.iter() {
+        if !ignored_header(name) && !a.headers.contains_key(name) {
+            result
+                .headers
+                .push((name.clone(), Change::Added(header_value(value))));
+        }
+    }
+
+    diff_values(&a.body, &b.body, String::new(), ignore, &mut result.body);
+    result
+}
+
+/// A header sent once as its value, and one sent several times as the list of them.
+pub fn header_value(values: &[String]) -> Value {
+    match values {
+        [value] => Value::String(value.clone()),
+        _ => Value::Array(values.iter().cloned().map(Value::String).collect()),
+    }
+}
+
+fn diff_values(
+    a: &Value,
+    b: &Value,
+    pointer: String,
+    ignore: &[String],
+    changes: &mut Vec<(String, Change)>,
+) {
+    if ignored_pointer(ignore, &pointer) {
+        return;
+    }
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (key, av) in a.iter() {
+                if ignore.iter().any(|i| i == key) {
+                    continue;
+                }
+                let child = format!("{}/{}", pointer, escape(key));
+                match b.get(key) {
+                    Some(bv) => diff_values(av, bv, child, ignore, changes),
+                    None if !ignored_pointer(ignore, &child) => {
+                        changes.push((child, Change::Removed(av.clone())))
+                    }
+                    None => {}
+                }
+            }
+            for (key, bv) in b.iter() {
+                if a.contains_key(key) || ignore.iter().any(|i| i == key) {
+                    continue;
+                }
+                let child = format!("{}/{}", pointer, escape(key));
+                if !ignored_pointer(ignore, &child) {
+                    changes.push((child, Change::Added(bv.clone())));
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for i in 0..a.len().max(b.len()) {
+                let child = format!("{}/{}", pointer, i);
+                match (a.get(i), b.get(i)) {
+                    (Some(av), Some(bv)) => diff_values(av, bv, child, ignore, changes),
+                    _ if ignored_pointer(ignore, &child) => {}
+                    (Some(av), None) => changes.push((child, Change::Removed(av.clone()))),
+                    (None, Some(bv)) => changes.push((child, Change::Added(bv.clone()))),
+                    (None, None) => unreachable!(),
+                }
+            }
+        }
+        _ => {
+            if a != b {
+                changes.push((pointer, Change::Changed(a.clone(), b.clone())));
+            }
+        }
+    }
+}
+
+/// Whether `pointer` is one of the JSON pointers in `ignore`.
+fn ignored_pointer(ignore: &[String], pointer: &str) -> bool {
+    ignore.iter().any(|i| i.starts_with('/') && i == pointer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn snapshot(body: Value) -> Snapshot {
+        Snapshot {
+            status: 200,
+            headers: BTreeMap::new(),
+            body,
+        }
+    }
+
+    #[test]
+    fn ignored_pointers_hide_added_and_removed_values() {
+        let ignore = vec!["/meta/request_id".to_owned(), "/items/1".to_owned()];
+        let a = snapshot(json!({"meta": {}, "items": [1, 2]}));
+        let b = snapshot(json!({"meta": {"request_id": "x"}, "items": [1]}));
+        assert!(diff(&a, &b, &ignore).is_empty());
+        assert!(diff(&b, &a, &ignore).is_empty());
+    }
+
+    #[test]
+    fn other_added_values_are_still_reported() {
+        let ignore = vec!["/meta/request_id".to_owned()];
+        let a = snapshot(json!({"meta": {}}));
+        let b = snapshot(json!({"meta": {"request_id": "x", "page": 2}}));
+        let result = diff(&a, &b, &ignore);
+        assert_eq!(result.body.len(), 1);
+        assert_eq!(result.body[0].0, "/meta/page");
+        assert!(matches!(result.body[0].1, Change::Added(_)));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::client::Payload;
+use crate::errors::{Error, HurlResult};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use log::{trace, warn};
+use reqwest::header::{HeaderValue, CONTENT_ENCODING};
+use reqwest::{Request, Response};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+/// The Accept-Encoding we send with --raw, when reqwest no longer sends its own.
+pub const ACCEPT_ALL: &str = "gzip, deflate, br";
+
+/// A content coding we can compress request bodies with and decode response bodies from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Gzip,
+    Br,
+    /// The zlib format, which is what HTTP means by deflate.
+    Deflate,
+}
+
+impl FromStr for Encoding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Ok(Encoding::Gzip),
+            "br" => Ok(Encoding::Br),
+            "deflate" => Ok(Encoding::Deflate),
+            _ => Err(Error::InvalidEncoding(s.to_owned())),
+        }
+    }
+}
+
+impl Encoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Br => "br",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    fn encode(self, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+            Encoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+            Encoding::Br => {
+                let mut out = Vec::new();
+                {
+                    let mut encoder = brotli::CompressorWriter::new(&mut out, 4096, 9, 22);
+                    encoder.write_all(bytes)?;
+                }
+                Ok(out)
+            }
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        match self {
+            Encoding::Gzip => GzDecoder::new(bytes).read_to_end(&mut out)?,
+            Encoding::Br => brotli::Decompressor::new(bytes, 4096).read_to_end(&mut out)?,
+            Encoding::Deflate => match ZlibDecoder::new(bytes).read_to_end(&mut out) {
+                Ok(n) => n,
+                // Some servers send a raw deflate stream without the zlib wrapper.
+                Err(_) => {
+                    out.clear();
+                    DeflateDecoder::new(bytes).read_to_end(&mut out)?
+                }
+            },
+        };
+        Ok(out)
+    }
+}
+
+/// Compresses the body of the request and marks it with Content-Encoding.
+///
+/// This happens after the body has been validated against the OpenAPI spec, which describes
+/// the uncompressed body, and before signing, which covers the bytes that are sent.
+pub fn compress(
+    request: &mut Request,
+    payload: Payload,
+    encoding: Encoding,
+) -> HurlResult<Payload> {
+    match payload {
+        Payload::Empty => Ok(Payload::Empty),
+        Payload::Streamed => Err(Error::StreamedBodyCompression),
+        Payload::Bytes {
+            content_type,
+            bytes,
+        } => {
+            let compressed = encoding.encode(&bytes).map_err(Error::IO)?;
+            trace!(
+                "Compressed the body with {} from {} to {} bytes",
+                encoding.as_str(),
+                bytes.len(),
+                compressed.len()
+            );
+            request.headers_mut().insert(
+                CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.as_str()),
+            );
+            *request.body_mut() = Some(compressed.clone().into());
+            Ok(Payload::Bytes {
+                content_type,
+                bytes: compressed,
+            })
+        }
+    }
+}
+
+/// The body of a response as text.
+pub struct Body {
+    pub text: String,
+    /// How many bytes were received, if the body had a Content-Encoding we decoded.
+    pub wire_len: Option<usize>,
+}
+
+/// Reads the body of a response, decoding it if it still has a Content-Encoding.
+///
+/// reqwest decodes gzip itself and removes the header unless it was built with `gzip(false)`,
+/// so a Content-Encoding which is still present means nobody has decoded the body yet. Codings
+/// are undone in the reverse of the order they were applied. If one is unknown, or the body
+/// does not decode, it is shown as it was received.
+pub fn read_body(resp: &mut Response) -> HurlResult<Body> {
+    let codings: Option<Vec<String>> = resp
+        .headers()
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(',')
+                .map(|c| c.trim().to_owned())
+                .filter(|c| !c.is_empty() && !c.eq_ignore_ascii_case("identity"))
+                .collect()
+        });
+    let codings = match codings {
+        Some(codings) if !codings.is_empty() => codings,
+        _ => {
+            return Ok(Body {
+                text: resp.text()?,
+                wire_len: None,
+            })
+        }
+    };
+
+    let mut raw = Vec::new();
+    resp.copy_to(&mut raw)?;
+    let wire_len = raw.len();
+    let mut bytes = raw.clone();
+    for coding in codings.iter().rev() {
+        let decoded = coding
+            .parse::<Encoding>()
+            .ok()
+            .and_then(|encoding| encoding.decode(&bytes).ok());
+        match decoded {
+            Some(decoded) => bytes = decoded,
+            None => {
+                warn!("Could not decode the {} body, showing it as received", coding);
+                bytes = raw;
+                break;
+            }
+        }
+    }
+    Ok(Body {
+        text: String::from_utf8_lossy(&bytes).into_owned(),
+        wire_len: Some(wire_len),
+    })
+}
@@ -1,54 +1,434 @@
+use crate::schema::Violation;
 use std::fmt;
+use std::path::PathBuf;
+use unicode_width::UnicodeWidthStr;
 
 pub enum Error {
     ParameterMissingSeparator(String),
+    /// A parameter could not be tokenized, e.g. because a quote is not closed.
+    ParameterSyntax {
+        param: String,
+        /// The byte offset of the offending character.
+        offset: usize,
+        message: String,
+    },
     MissingUrlAndCommand,
-    NotFormButHasFormFile,
-    ClientSerialization,
-    ClientTimeout,
-    ClientWithStatus(reqwest::StatusCode),
-    ClientOther,
-    SerdeJson(serde_json::error::Category),
-    IO(std::io::ErrorKind),
-    UrlParseError(reqwest::UrlError),
+    ClientSerialization(reqwest::Error),
+    ClientTimeout(reqwest::Error),
+    ClientWithStatus {
+        status: reqwest::StatusCode,
+        source: reqwest::Error,
+    },
+    ClientOther(reqwest::Error),
+    SerdeJson(serde_json::Error),
+    IO(std::io::Error),
+    UrlParseError {
+        url: String,
+        source: reqwest::UrlError,
+    },
     SyntaxLoadError(&'static str),
+    InvalidRetryCondition(String),
+    /// A file given on the command line or used for configuration could not be accessed.
+    File {
+        path: PathBuf,
+        access: Access,
+        source: std::io::Error,
+    },
+    /// The configuration file exists but is not valid TOML for our `Config`.
+    ConfigParse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    /// A stored session exists but could not be decoded.
+    SessionParse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// A baseline given with --compare-to could not be decoded.
+    BaselineParse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// The compared responses are not the same.
+    ResponsesDiffer(usize),
+    /// The value of a `key:=value` parameter is not valid JSON.
+    ParameterJson {
+        key: String,
+        value: String,
+        source: serde_json::Error,
+    },
+    /// The contents of the file in a `key:=@filename` parameter are not valid JSON.
+    ParameterJsonFile {
+        key: String,
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// The MIME type given with `;type=` in a `key@filename` parameter could not be parsed.
+    InvalidMimeType {
+        key: String,
+        mime: String,
+    },
+    /// The data parameters could not be encoded as a form, e.g. because a value is nested JSON.
+    FormEncoding(serde_urlencoded::ser::Error),
+    /// The profile given with --profile is not in the configuration file.
+    UnknownProfile(String),
+    /// The signer configured for the active profile could not sign the request.
+    Signing(String),
+    /// The OpenAPI spec configured for the active profile could not be parsed.
+    SpecParse {
+        path: PathBuf,
+        message: String,
+    },
+    /// A command needs an OpenAPI spec but the active profile does not have one.
+    MissingSpec,
+    /// The interval given with --watch could not be parsed.
+    InvalidDuration(String),
+    /// The condition given with --until could not be parsed.
+    InvalidCondition {
+        expr: String,
+        message: String,
+    },
+    /// The override given with --resolve is not of the form `host:port:address`.
+    InvalidResolve(String),
+    /// The Unix socket given with --unix-socket could not be used.
+    Socket {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// A multipart body cannot be sent over a Unix socket.
+    StreamedBodyOverSocket,
+    /// The coding given with --compress is not one we support.
+    InvalidEncoding(String),
+    /// A multipart body is streamed and cannot be compressed.
+    StreamedBodyCompression,
+    /// A response read from a Unix socket is not valid HTTP.
+    MalformedResponse(String),
+    /// The id given to `hurl replay` is not in the history.
+    UnknownHistoryEntry(u64),
+    /// The credential helper of the profile failed or did not print a token.
+    CredentialHelper {
+        command: String,
+        message: String,
+    },
+    /// The request does not match its operation in the OpenAPI spec.
+    InvalidRequest {
+        operation: String,
+        violations: Vec<Violation>,
+    },
+    /// The response does not match its operation in the OpenAPI spec.
+    InvalidResponse {
+        operation: String,
+        violations: Vec<Violation>,
+    },
+    /// A file given with --schema is not a JSON Schema document.
+    SchemaParse {
+        path: PathBuf,
+        message: String,
+    },
+    /// The response body does not match the schema given with --schema.
+    SchemaMismatch {
+        path: PathBuf,
+        violations: Vec<Violation>,
+    },
 }
 
 pub type HurlResult<T> = Result<T, Error>;
 
+/// What we were doing with a file when `Error::File` happened.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Error {
+    /// A suggestion for the user on how to fix the problem, if we have one.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Error::ParameterMissingSeparator(_) => Some(
+                "parameters take the form key:value, key=value, key:=value, key==value, \
+                 key@filename, key=@filename or key:=@filename; quote or escape a literal \
+                 separator in the key, e.g. 'a:b'=c or a\\:b=c"
+                    .to_owned(),
+            ),
+            Error::ParameterSyntax { .. } => Some(
+                "quote a key with '...' or \"...\", or escape a separator or quote with `\\`"
+                    .to_owned(),
+            ),
+            Error::MissingUrlAndCommand => {
+                Some("try `hurl example.com` or `hurl GET example.com`".to_owned())
+            }
+            Error::ClientTimeout(_) => {
+                Some("use --retry N --retry-on timeout to try again automatically".to_owned())
+            }
+            Error::UrlParseError { url, .. } if url.starts_with(':') => {
+                Some("the shorthand for localhost is `:port/path`, e.g. `:8080/foo`".to_owned())
+            }
+            Error::File {
+                path,
+                access: Access::Read,
+                source,
+            } if path.is_relative() && source.kind() == std::io::ErrorKind::NotFound =>
+            {
+                Some("relative paths are resolved from the current directory".to_owned())
+            }
+            Error::ConfigParse { .. } => Some(
+                "supported keys are verbose, form, auth, token, secure, cache, ignore, history \
+                 and profiles, and a profile may set signer, openapi and credential_helper; set \
+                 HURL_CONFIG or --config to use another file"
+                    .to_owned(),
+            ),
+            Error::SessionParse { path, .. } => Some(format!(
+                "delete {} to start the session over",
+                path.display()
+            )),
+            Error::BaselineParse { .. } => {
+                Some("baselines are written with --save-baseline".to_owned())
+            }
+            Error::ResponsesDiffer(_) => {
+                Some("use --ignore to skip keys which are expected to change".to_owned())
+            }
+            Error::ParameterJson { key, value, .. } => Some(format!(
+                "`:=` sends raw JSON; to send a string, did you mean `{}={}`?",
+                key, value
+            )),
+            Error::ParameterJsonFile { key, path, .. } => Some(format!(
+                "`:=@` sends the file as raw JSON; to send its contents as a string, did you \
+                 mean `{}=@{}`?",
+                key,
+                path.display()
+            )),
+            Error::InvalidMimeType { .. } => {
+                Some("MIME types look like `type/subtype`, e.g. `image/png`".to_owned())
+            }
+            Error::FormEncoding(_) => Some(
+                "only flat string and number values can be form encoded; drop --form to send \
+                 JSON"
+                    .to_owned(),
+            ),
+            Error::UnknownProfile(_) => {
+                Some("profiles are defined as [profiles.NAME] in the configuration file".to_owned())
+            }
+            Error::SpecParse { .. } => Some(
+                "the spec is set with `openapi` in the profile and may be YAML if the file ends \
+                 in .yaml or .yml"
+                    .to_owned(),
+            ),
+            Error::MissingSpec => Some(
+                "set `openapi` in a profile of the configuration file and select it with \
+                 --profile"
+                    .to_owned(),
+            ),
+            Error::InvalidDuration(_) => {
+                Some("durations are a number with a unit of ms, s, m or h, e.g. 5s".to_owned())
+            }
+            Error::InvalidCondition { .. } => Some(
+                "conditions look like `json.state == \"done\"`, `json.items[0].id != null` or \
+                 `status == 200`"
+                    .to_owned(),
+            ),
+            Error::InvalidResolve(_) => Some(
+                "--resolve takes host:port:address, e.g. example.com:443:10.0.0.5 or \
+                 example.com:80:[::1]"
+                    .to_owned(),
+            ),
+            Error::Socket { source, .. }
+                if source.kind() == std::io::ErrorKind::PermissionDenied =>
+            {
+                Some(
+                    "check that you may write to the socket, e.g. are in the docker group"
+                        .to_owned(),
+                )
+            }
+            Error::StreamedBodyOverSocket => {
+                Some("send the data as JSON or a form instead of key@filename".to_owned())
+            }
+            Error::InvalidEncoding(_) => {
+                Some("--compress takes one of gzip, br or deflate".to_owned())
+            }
+            Error::StreamedBodyCompression => {
+                Some("send the data as JSON or a form instead of key@filename".to_owned())
+            }
+            Error::UnknownHistoryEntry(_) => {
+                Some("run `hurl history` to list the recorded requests".to_owned())
+            }
+            Error::CredentialHelper { .. } => Some(
+                "the helper is set with `credential_helper` in the profile and should print a \
+                 token on its first line of output"
+                    .to_owned(),
+            ),
+            Error::InvalidRequest { .. } => {
+                Some("pass --no-validate to send the request anyway".to_owned())
+            }
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::ParameterMissingSeparator(s) => {
-                write!(f, "Missing separator when parsing parameter: {}", s)
+                write!(f, "Missing separator when parsing parameter")?;
+                write_caret(f, s, s.len())
+            }
+            Error::ParameterSyntax {
+                param,
+                offset,
+                message,
+            } => {
+                write!(f, "Invalid parameter: {}", message)?;
+                write_caret(f, param, *offset)
             }
             Error::MissingUrlAndCommand => write!(f, "Must specify a url or a command!"),
-            Error::NotFormButHasFormFile => write!(
+            Error::ClientSerialization(_) => write!(f, "serializing the request/response failed"),
+            Error::ClientTimeout(e) => match e.url() {
+                Some(url) => write!(f, "Timeout during request to {}", url),
+                None => write!(f, "Timeout during request"),
+            },
+            Error::ClientWithStatus { status, .. } => write!(f, "Got status code: {}", status),
+            Error::ClientOther(e) => match e.url() {
+                Some(url) => write!(f, "Request to {} failed", url),
+                None => write!(f, "Request failed"),
+            },
+            Error::SerdeJson(_) => write!(f, "JSON error"),
+            Error::IO(_) => write!(f, "IO Error"),
+            Error::UrlParseError { url, .. } => write!(f, "Could not parse URL '{}'", url),
+            Error::SyntaxLoadError(typ) => write!(f, "Error loading syntax for {}", typ),
+            Error::InvalidRetryCondition(s) => write!(
                 f,
-                "Cannot have a form file 'key@filename' unless --form option is set",
+                "Invalid retry condition '{}', expected a status code, 'timeout' or 'error'",
+                s
             ),
-            Error::ClientSerialization => write!(f, "serializing the request/response failed"),
-            Error::ClientTimeout => write!(f, "Timeout during request"),
-            Error::ClientWithStatus(status) => write!(f, "Got status conde: {}", status),
-            Error::ClientOther => write!(f, "Unknown client error"),
-            Error::SerdeJson(c) => write!(f, "JSON error: {:?}", c),
-            Error::IO(k) => write!(f, "IO Error: {:?}", k),
-            Error::UrlParseError(e) => write!(f, "URL Parsing Error: {}", e),
-            Error::SyntaxLoadError(typ) => write!(f, "Error loading syntax for {}", typ),
+            Error::File { path, access, .. } => match access {
+                Access::Read => write!(f, "Could not read {}", path.display()),
+                Access::Write => write!(f, "Could not write {}", path.display()),
+            },
+            Error::ConfigParse { path, .. } => {
+                write!(f, "Invalid configuration file {}", path.display())
+            }
+            Error::SessionParse { path, .. } => {
+                write!(f, "Invalid session file {}", path.display())
+            }
+            Error::BaselineParse { path, .. } => {
+                write!(f, "Invalid baseline file {}", path.display())
+            }
+            Error::ResponsesDiffer(n) => write!(
+                f,
+                "Responses differ in {} place{}",
+                n,
+                if *n == 1 { "" } else { "s" }
+            ),
+            Error::ParameterJson { key, value, .. } => {
+                write!(f, "Value of parameter '{}:={}' is not valid JSON", key, value)
+            }
+            Error::ParameterJsonFile { key, path, .. } => write!(
+                f,
+                "File {} given for parameter '{}' is not valid JSON",
+                path.display(),
+                key
+            ),
+            Error::InvalidMimeType { key, mime } => {
+                write!(f, "Invalid MIME type '{}' for part '{}'", mime, key)
+            }
+            Error::FormEncoding(_) => write!(f, "Could not encode the data as a form"),
+            Error::UnknownProfile(name) => write!(f, "Unknown profile '{}'", name),
+            Error::Signing(msg) => write!(f, "Could not sign the request: {}", msg),
+            Error::SpecParse { path, message } => {
+                write!(f, "Invalid OpenAPI spec {}: {}", path.display(), message)
+            }
+            Error::MissingSpec => write!(f, "No OpenAPI spec is configured"),
+            Error::InvalidDuration(s) => write!(f, "Invalid duration '{}'", s),
+            Error::InvalidCondition { expr, message } => {
+                write!(f, "Invalid condition '{}': {}", expr, message)
+            }
+            Error::InvalidResolve(s) => write!(f, "Invalid --resolve '{}'", s),
+            Error::Socket { path, .. } => {
+                write!(f, "Could not talk to the socket {}", path.display())
+            }
+            Error::StreamedBodyOverSocket => {
+                write!(f, "Multipart bodies cannot be sent over a Unix socket")
+            }
+            Error::InvalidEncoding(s) => write!(f, "Unsupported content encoding '{}'", s),
+            Error::StreamedBodyCompression => write!(f, "Multipart bodies cannot be compressed"),
+            Error::MalformedResponse(msg) => write!(f, "Malformed response: {}", msg),
+            Error::UnknownHistoryEntry(id) => write!(f, "No request {} in the history", id),
+            Error::CredentialHelper { command, message } => {
+                write!(f, "Credential helper `{}` failed: {}", command, message)
+            }
+            Error::InvalidRequest {
+                operation,
+                violations,
+            } => {
+                write!(f, "Request does not match operation {}", operation)?;
+                write_violations(f, violations)
+            }
+            Error::InvalidResponse {
+                operation,
+                violations,
+            } => {
+                write!(f, "Response does not match operation {}", operation)?;
+                write_violations(f, violations)
+            }
+            Error::SchemaParse { path, message } => {
+                write!(f, "Invalid JSON Schema {}: {}", path.display(), message)
+            }
+            Error::SchemaMismatch { path, violations } => {
+                write!(f, "Response does not match schema {}", path.display())?;
+                write_violations(f, violations)
+            }
         }
     }
 }
 
+/// Writes `src` with a caret under the character at byte `offset`, which may be the end.
+fn write_caret(f: &mut fmt::Formatter<'_>, src: &str, offset: usize) -> fmt::Result {
+    let width = src.get(..offset).map_or(0, UnicodeWidthStr::width);
+    write!(f, "\n    {}\n    {}^", src, " ".repeat(width))
+}
+
+fn write_violations(f: &mut fmt::Formatter<'_>, violations: &[Violation]) -> fmt::Result {
+    for v in violations.iter() {
+        write!(f, "\n    {}", v)?;
+    }
+    Ok(())
+}
+
+// `main` returns a `HurlResult`, so this is what the user sees when something goes wrong. We
+// render the error, the chain of errors which caused it, and a hint if there is one.
 impl fmt::Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self)
+        write!(f, "{}", self)?;
+        let mut source = std::error::Error::source(self);
+        while let Some(cause) = source {
+            write!(f, "\n  caused by: {}", cause)?;
+            source = cause.source();
+        }
+        if let Some(hint) = self.hint() {
+            write!(f, "\n  hint: {}", hint)?;
+        }
+        Ok(())
     }
 }
 
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            Error::UrlParseError(e) => Some(e),
+            Error::ClientSerialization(e) => Some(e),
+            Error::ClientTimeout(e) => Some(e),
+            Error::ClientWithStatus { source, .. } => Some(source),
+            Error::ClientOther(e) => Some(e),
+            Error::SerdeJson(e) => Some(e),
+            Error::IO(e) => Some(e),
+            Error::UrlParseError { source, .. } => Some(source),
+            Error::File { source, .. } => Some(source),
+            Error::ConfigParse { source, .. } => Some(source),
+            Error::SessionParse { source, .. } => Some(source),
+            Error::BaselineParse { source, .. } => Some(source),
+            Error::ParameterJson { source, .. } => Some(source),
+            Error::ParameterJsonFile { source, .. } => Some(source),
+            Error::FormEncoding(e) => Some(e),
+            Error::Socket { source, .. } => Some(source),
             _ => None,
         }
     }
@@ -58,35 +438,38 @@ impl From<reqwest::Error> for Error {
     #[inline]
     fn from(err: reqwest::Error) -> Error {
         if err.is_serialization() {
-            return Error::ClientSerialization;
+            return Error::ClientSerialization(err);
         }
         if err.is_timeout() {
-            return Error::ClientTimeout;
+            return Error::ClientTimeout(err);
         }
-        if let Some(s) = err.status() {
-            return Error::ClientWithStatus(s);
+        if let Some(status) = err.status() {
+            return Error::ClientWithStatus {
+                status,
+                source: err,
+            };
         }
-        Error::ClientOther
+        Error::ClientOther(err)
     }
 }
 
 impl From<serde_json::error::Error> for Error {
     #[inline]
     fn from(err: serde_json::error::Error) -> Error {
-        Error::SerdeJson(err.classify())
+        Error::SerdeJson(err)
     }
 }
 
 impl From<std::io::Error> for Error {
     #[inline]
     fn from(err: std::io::Error) -> Error {
-        Error::IO(err.kind())
+        Error::IO(err)
     }
 }
 
-impl From<reqwest::UrlError> for Error {
+impl From<serde_urlencoded::ser::Error> for Error {
     #[inline]
-    fn from(err: reqwest::UrlError) -> Error {
-        Error::UrlParseError(err)
+    fn from(err: serde_urlencoded::ser::Error) -> Error {
+        Error::FormEncoding(err)
     }
 }
@@ -0,0 +1,210 @@
+use crate::app::{App, Parameter};
+use crate::directories::DIRECTORIES;
+use crate::encoding::Encoding;
+use crate::errors::{Access, Error, HurlResult};
+use crate::transport::Resolve;
+use chrono::{DateTime, Local};
+use log::{trace, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How many entries are kept. Older entries are dropped once the history grows past this.
+const MAX_ENTRIES: usize = 1000;
+
+/// A request hurl sent and what came of it.
+///
+/// The parameters are stored already parsed, so a replay sends exactly what was sent before
+/// without having to escape separators again. They include any credentials given as headers,
+/// which is why the history file can only be read by its owner.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    pub time: DateTime<Local>,
+    #[serde(with = "method")]
+    pub method: reqwest::Method,
+    /// The URL as it was given on the command line, which also names the session.
+    pub url: String,
+    pub parameters: Vec<Parameter>,
+    pub session: Option<String>,
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub form: bool,
+    #[serde(default)]
+    pub secure: bool,
+    #[serde(default)]
+    pub unix_socket: Option<PathBuf>,
+    #[serde(default)]
+    pub resolve: Vec<Resolve>,
+    #[serde(default)]
+    pub compress: Option<Encoding>,
+    #[serde(default)]
+    pub raw: bool,
+    /// The status of the response, or None if no response was received.
+    pub status: Option<u16>,
+    pub elapsed_ms: u64,
+    pub error: Option<String>,
+}
+
+impl Entry {
+    pub fn new(
+        app: &App,
+        method: reqwest::Method,
+        url: &str,
+        parameters: &[Parameter],
+        elapsed: Duration,
+    ) -> Entry {
+        Entry {
+            id: 0,
+            time: Local::now(),
+            method,
+            url: url.to_owned(),
+            parameters: parameters.to_vec(),
+            session: app.session.clone(),
+            profile: app.profile.clone(),
+            form: app.form,
+            secure: app.secure,
+            unix_socket: app.unix_socket.clone(),
+            resolve: app.resolve.clone(),
+            compress: app.compress,
+            raw: app.raw,
+            status: None,
+            elapsed_ms: elapsed.as_millis() as u64,
+            error: None,
+        }
+    }
+}
+
+fn path() -> PathBuf {
+    DIRECTORIES.config().join("history.jsonl")
+}
+
+/// Reads every entry, oldest first. Lines which cannot be decoded are skipped.
+pub fn load() -> HurlResult<Vec<Entry>> {
+    let path = path();
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(source) => {
+            return Err(Error::File {
+                path,
+                access: Access::Read,
+                source,
+            })
+        }
+    };
+    let mut entries = Vec::new();
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|source| Error::File {
+            path: path.clone(),
+            access: Access::Read,
+            source,
+        })?;
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => warn!("Skipping line {} of {}: {}", n + 1, path.display(), e),
+        }
+    }
+    Ok(entries)
+}
+
+pub fn find(id: u64) -> HurlResult<Entry> {
+    load()?
+        .into_iter()
+        .find(|e| e.id == id)
+        .ok_or(Error::UnknownHistoryEntry(id))
+}
+
+/// Appends an entry, numbering it after the newest one.
+pub fn record(mut entry: Entry) -> HurlResult<()> {
+    let path = path();
+    let mut entries = load()?;
+    entry.id = entries.last().map_or(1, |last| last.id + 1);
+    trace!("Recording request {} in the history", entry.id);
+
+    let dir = DIRECTORIES.config();
+    create_dir_all(dir).map_err(|source| Error::File {
+        path: dir.to_owned(),
+        access: Access::Write,
+        source,
+    })?;
+    let file_error = |source| Error::File {
+        path: path.clone(),
+        access: Access::Write,
+        source,
+    };
+    if entries.len() < MAX_ENTRIES {
+        let file = open_private(&path, true).map_err(file_error)?;
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        return BufWriter::new(file)
+            .write_all(line.as_bytes())
+            .map_err(file_error);
+    }
+
+    entries.push(entry);
+    let file = open_private(&path, false).map_err(file_error)?;
+    let mut writer = BufWriter::new(file);
+    for entry in entries[entries.len() - MAX_ENTRIES..].iter() {
+        serde_json::to_writer(&mut writer, entry)?;
+        writer.write_all(b"\n").map_err(file_error)?;
+    }
+    writer.flush().map_err(file_error)
+}
+
+// Opens the history for appending or rewriting, readable only by its owner. A history written
+// before this was enforced keeps its mode when opened, so it is tightened as well.
+fn open_private(path: &Path, append: bool) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options
+        .create(true)
+        .append(append)
+        .write(true)
+        .truncate(!append);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        let file = options.mode(0o600).open(path)?;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        Ok(file)
+    }
+    #[cfg(not(unix))]
+    options.open(path)
+}
+
+/// Prints the most recent `count` entries, oldest first.
+pub fn print(entries: &[Entry], count: usize) {
+    let start = entries.len().saturating_sub(count);
+    for entry in entries[start..].iter() {
+        let outcome = match (entry.status, &entry.error) {
+            (Some(status), _) => status.to_string(),
+            (None, Some(_)) => "ERR".to_owned(),
+            (None, None) => "-".to_owned(),
+        };
+        println!(
+            "{:>5}  {}  {:6} {:>4} {:>7}  {}",
+            entry.id,
+            entry.time.format("%Y-%m-%d %H:%M:%S"),
+            entry.method.as_str(),
+            outcome,
+            format!("{}ms", entry.elapsed_ms),
+            entry.url
+        );
+    }
+}
+
+/// `reqwest::Method` has no serde support, so it is stored as its name.
+mod method {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(method: &reqwest::Method, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(method.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<reqwest::Method, D::Error> {
+        let name = String::deserialize(d)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
@@ -5,21 +5,41 @@ use syntect::highlighting::Theme;
 use syntect::parsing::SyntaxSet;
 
 mod app;
+mod cache;
 mod client;
 mod config;
+mod credentials;
+mod diff;
 mod directories;
+mod encoding;
 mod errors;
+mod history;
+mod multipart;
+mod openapi;
+mod retry;
+mod schema;
 mod session;
+mod sign;
 mod syntax;
+mod transport;
+mod watch;
 
-use errors::HurlResult;
+use errors::{Error, HurlResult};
 
 type OrderedJson = std::collections::BTreeMap<String, serde_json::Value>;
 
 fn main() -> HurlResult<()> {
     let mut app = app::App::from_args();
     app.validate()?;
-    app.process_config_file();
+    let replayed = match app.cmd {
+        Some(app::Command::Replay(ref data)) => Some(history::find(data.id)?),
+        _ => None,
+    };
+    if let Some(entry) = &replayed {
+        app.replay_from(entry);
+    }
+    app.process_config_file()?;
+    app.load_schema()?;
 
     if let Some(level) = app.log_level() {
         std::env::set_var("RUST_LOG", format!("hurl={}", level));
@@ -29,15 +49,53 @@ fn main() -> HurlResult<()> {
     let (ss, ts) = syntax::build()?;
     let theme = &ts.themes["Solarized (dark)"];
 
+    // A replayed request is stored in the session of the host it was originally sent to.
+    let host = match &replayed {
+        Some(entry) => session::make_safe_pathname(&entry.url),
+        None => app.host(),
+    };
     let mut session = app
         .session
         .as_ref()
-        .map(|name| session::Session::get_or_create(&app, name.clone(), app.host()));
+        .map(|name| session::Session::get_or_create(&app, name.clone(), host.clone()))
+        .transpose()?;
 
     match app.cmd {
-        Some(ref method) => {
-            let resp = client::perform_method(&app, method, &mut session)?;
-            handle_response(&app, &ss, theme, resp, &mut session)
+        Some(app::Command::Method(ref method)) if app.watch.is_some() => {
+            let data = method.data();
+            watch::run(
+                &app,
+                &ss,
+                theme,
+                method.into(),
+                &data.url,
+                &data.parameters,
+                &mut session,
+            )
+        }
+        Some(app::Command::Method(ref method)) => {
+            let client = client::build_client(&app)?;
+            let exchange = client::perform_method(&app, &client, method, &mut session)?;
+            handle_response(&app, &ss, theme, exchange, &mut session)
+        }
+        Some(app::Command::Diff(ref data)) => handle_diff(&app, data),
+        Some(app::Command::Ops(ref data)) => handle_ops(&app, data),
+        Some(app::Command::History(ref data)) => {
+            history::print(&history::load()?, data.count);
+            Ok(())
+        }
+        Some(app::Command::Replay(_)) => {
+            let entry = replayed.unwrap();
+            let client = client::build_client(&app)?;
+            let exchange = client::perform_recorded(
+                &app,
+                &client,
+                entry.method,
+                &mut session,
+                &entry.url,
+                &entry.parameters,
+            )?;
+            handle_response(&app, &ss, theme, exchange, &mut session)
         }
         None => {
             let url = app.url.take().unwrap();
@@ -47,8 +105,27 @@ fn main() -> HurlResult<()> {
             } else {
                 reqwest::Method::GET
             };
-            let resp = client::perform(&app, method, &mut session, &url, &app.parameters)?;
-            handle_response(&app, &ss, theme, resp, &mut session)
+            if app.watch.is_some() {
+                return watch::run(
+                    &app,
+                    &ss,
+                    theme,
+                    method,
+                    &url,
+                    &app.parameters,
+                    &mut session,
+                );
+            }
+            let client = client::build_client(&app)?;
+            let exchange = client::perform_recorded(
+                &app,
+                &client,
+                method,
+                &mut session,
+                &url,
+                &app.parameters,
+            )?;
+            handle_response(&app, &ss, theme, exchange, &mut session)
         }
     }
 }
@@ -57,9 +134,10 @@ fn handle_response(
     app: &app::App,
     ss: &SyntaxSet,
     theme: &Theme,
-    mut resp: reqwest::Response,
+    exchange: client::Exchange,
     session: &mut Option<session::Session>,
 ) -> HurlResult<()> {
+    let mut resp = exchange.response;
     let status = resp.status();
     let mut s = format!(
         "{:?} {} {}\n",
@@ -69,6 +147,10 @@ fn handle_response(
     );
     let mut headers = Vec::new();
     for (key, value) in resp.headers().iter() {
+        // Content-Length is added below, once we know the size of the body.
+        if key == reqwest::header::CONTENT_LENGTH {
+            continue;
+        }
         let nice_key = key.as_str().to_title_case().replace(' ', "-");
         headers.push(format!(
             "{}: {}",
@@ -78,19 +160,52 @@ fn handle_response(
     }
     // Reqwest returns a None when the content length of the response is not the same as the
     // Content-Length header value, which is likely due to compression. In this case, we use
-    // the length of the decompressed body as the return value for the Content-Length.
-    let result = resp.text()?;
+    // the length of the decompressed body as the return value for the Content-Length. With
+    // --raw we decode the body ourselves and show both sizes.
+    let body = encoding::read_body(&mut resp)?;
+    let mut result = body.text;
+    let mut from_cache = false;
+    if app.cache {
+        let key = cache::key(&exchange.method, &exchange.url);
+        if let (Some(s), Some(key)) = (session.as_mut(), key) {
+            if status == reqwest::StatusCode::NOT_MODIFIED {
+                if let Some(entry) = s.cached(&key) {
+                    result = entry.body().to_owned();
+                    from_cache = true;
+                }
+            } else if let Some(entry) = cache::CacheEntry::from_response(&resp, &result) {
+                s.update_cache(key, entry);
+            }
+        }
+    }
     let content_length = match resp.content_length() {
         Some(len) => len,
         None => result.len() as u64,
     };
-    headers.push(format!("Content-Length: {}", content_length));
+    match body.wire_len {
+        Some(wire_len) => headers.push(format!(
+            "Content-Length: {} ({} decoded)",
+            wire_len,
+            result.len()
+        )),
+        None => headers.push(format!("Content-Length: {}", content_length)),
+    }
     headers.sort();
 
     // Since we can call `join` on a Vec, we convert it into a slice of type `&[String]`.
     // We then put another `&` in front to convert String to &str.
     s.push_str(&(&headers[..]).join("\n"));
     highlight_string(ss, theme, "HTTP", &s);
+    if !app.quiet && app.verbose > 0 {
+        let protocol = transport::describe_protocol(app, &exchange.url, resp.version());
+        println!("Protocol: {}", protocol);
+    }
+    if app.retry > 0 {
+        println!("Attempts: {}", exchange.attempts);
+    }
+    if from_cache {
+        println!("Body served from the session cache");
+    }
     println!("");
     let result_json: serde_json::Result<OrderedJson> = serde_json::from_str(&result);
     match result_json {
@@ -111,9 +226,105 @@ fn handle_response(
         }
     }
 
+    if !app.no_validate {
+        if let Some(spec) = &app.openapi {
+            spec.check_response(&exchange.method, &exchange.url, &resp, &result)?;
+        }
+    }
+
+    if let (Some(path), Some(doc)) = (&app.schema, &app.response_schema) {
+        let violations = match serde_json::from_str(&result) {
+            Ok(body) => schema::validate(doc, doc, &body),
+            Err(e) => vec![schema::Violation {
+                location: String::new(),
+                message: format!("the body is not JSON: {}", e),
+            }],
+        };
+        if !violations.is_empty() {
+            return Err(Error::SchemaMismatch {
+                path: path.clone(),
+                violations,
+            });
+        }
+    }
+
+    if app.compare_to.is_some() || app.save_baseline.is_some() {
+        let snapshot = diff::Snapshot::from_response(&resp, &result);
+        if let Some(path) = &app.save_baseline {
+            snapshot.save(path)?;
+        }
+        if let Some(path) = &app.compare_to {
+            let baseline = diff::Snapshot::load(path)?;
+            println!();
+            report_diff(&baseline, &snapshot, &app.ignore)?;
+        }
+    }
+
     Ok(())
 }
 
+fn handle_diff(app: &app::App, data: &app::DiffData) -> HurlResult<()> {
+    let client = client::build_client(app)?;
+    let mut snapshots = Vec::with_capacity(2);
+    for url in [&data.url_a, &data.url_b].iter() {
+        // The two URLs are usually on different hosts, so each is sent with the session of its
+        // own host, as it would be if it were requested on its own.
+        let mut session = app
+            .session
+            .as_ref()
+            .map(|name| {
+                let host = session::make_safe_pathname(url);
+                session::Session::get_or_create(app, name.clone(), host)
+            })
+            .transpose()?;
+        let exchange = client::perform(
+            app,
+            &client,
+            data.method.clone(),
+            &mut session,
+            url,
+            &data.parameters,
+        )?;
+        let mut resp = exchange.response;
+        let body = encoding::read_body(&mut resp)?.text;
+        snapshots.push(diff::Snapshot::from_response(&resp, &body));
+    }
+    report_diff(&snapshots[0], &snapshots[1], &app.ignore)
+}
+
+fn handle_ops(app: &app::App, data: &app::OpsData) -> HurlResult<()> {
+    let spec = app.openapi.as_ref().ok_or(Error::MissingSpec)?;
+    let filter = data.filter.as_ref().map(|f| f.to_lowercase());
+    for op in spec.operations() {
+        let summary = op.summary.unwrap_or("");
+        if let Some(filter) = &filter {
+            let haystack = format!("{} {} {}", op.path, op.id.unwrap_or(""), summary);
+            if !haystack.to_lowercase().contains(filter) {
+                continue;
+            }
+        }
+        let line = format!(
+            "{:7} {:30} {:20} {}",
+            op.method.to_uppercase(),
+            op.path,
+            op.id.unwrap_or("-"),
+            summary
+        );
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
+
+fn report_diff(a: &diff::Snapshot, b: &diff::Snapshot, ignore: &[String]) -> HurlResult<()> {
+    let changes = diff::diff(a, b, ignore);
+    if changes.is_empty() {
+        println!("No differences");
+        return Ok(());
+    }
+    changes.print();
+    Err(Error::ResponsesDiffer(changes.len()))
+}
+
 fn highlight_string(ss: &SyntaxSet, theme: &Theme, syntax: &str, string: &str) {
     use syntect::easy::HighlightLines;
     use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
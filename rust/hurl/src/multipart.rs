@@ -0,0 +1,103 @@
+use crate::errors::{Access, Error, HurlResult};
+use log::trace;
+use reqwest::multipart::Part;
+use serde_json::Value;
+use std::io::Read;
+use std::path::PathBuf;
+
+/// The path used in a `key@path` parameter to read the part from stdin.
+const STDIN: &str = "-";
+
+/// The value of a `key@filename` parameter.
+///
+/// The filename may be followed by options separated with `;`, e.g.
+/// `photo.png;type=image/png;filename=avatar.png`. `type` overrides the MIME type of the part,
+/// which is otherwise guessed from the file extension, and `filename` overrides the file name
+/// which is sent to the server.
+#[derive(Debug, PartialEq)]
+pub struct FileSpec {
+    pub path: String,
+    pub mime: Option<String>,
+    pub file_name: Option<String>,
+}
+
+impl FileSpec {
+    pub fn parse(value: &str) -> FileSpec {
+        let mut segments: Vec<&str> = value.split(';').collect();
+        let mut mime = None;
+        let mut file_name = None;
+        // Options are only recognized at the end so that a `;` elsewhere in a path still works.
+        while segments.len() > 1 {
+            let last = segments[segments.len() - 1];
+            if let Some(m) = strip_option(last, "type") {
+                mime = mime.or_else(|| Some(m.to_owned()));
+            } else if let Some(f) = strip_option(last, "filename") {
+                file_name = file_name.or_else(|| Some(f.to_owned()));
+            } else {
+                break;
+            }
+            segments.pop();
+        }
+        FileSpec {
+            path: segments.join(";"),
+            mime,
+            file_name,
+        }
+    }
+
+    pub fn is_stdin(&self) -> bool {
+        self.path == STDIN
+    }
+
+    /// Builds the part, reading the file or stdin.
+    pub fn into_part(self, key: &str) -> HurlResult<Part> {
+        let mut part = if self.is_stdin() {
+            trace!("Reading part {} from stdin", key);
+            let mut buf = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut buf)
+                .map_err(|source| Error::File {
+                    path: PathBuf::from(STDIN),
+                    access: Access::Read,
+                    source,
+                })?;
+            Part::bytes(buf)
+        } else {
+            Part::file(&self.path).map_err(|source| Error::File {
+                path: PathBuf::from(&self.path),
+                access: Access::Read,
+                source,
+            })?
+        };
+        if let Some(file_name) = self.file_name {
+            part = part.file_name(file_name);
+        }
+        if let Some(mime) = self.mime {
+            part = with_mime(part, key, &mime)?;
+        }
+        Ok(part)
+    }
+}
+
+/// A part holding JSON, for `key:=value` and `key:=@filename` parameters in a multipart body.
+pub fn json_part(key: &str, value: &Value) -> HurlResult<Part> {
+    with_mime(Part::text(value.to_string()), key, "application/json")
+}
+
+fn with_mime(part: Part, key: &str, mime: &str) -> HurlResult<Part> {
+    part.mime_str(mime).map_err(|_| Error::InvalidMimeType {
+        key: key.to_owned(),
+        mime: mime.to_owned(),
+    })
+}
+
+fn strip_option<'a>(segment: &'a str, name: &str) -> Option<&'a str> {
+    let mut parts = segment.splitn(2, '=');
+    let key = parts.next()?.trim();
+    let value = parts.next()?;
+    if key.eq_ignore_ascii_case(name) && !value.is_empty() {
+        Some(value)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,412 @@
+use crate::client::Payload;
+use crate::errors::{Access, Error, HurlResult};
+use crate::schema::{self, Violation};
+use log::{debug, trace, warn};
+use reqwest::{Method, Request, Response, Url};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// The methods an OpenAPI path item may describe, in the order operations are listed.
+const METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// An OpenAPI 3 document describing the service a profile talks to.
+#[derive(Debug)]
+pub struct Spec {
+    doc: Value,
+}
+
+/// A single operation, i.e. a method on a path, in a spec.
+pub struct Operation<'s> {
+    pub method: &'static str,
+    pub path: &'s str,
+    pub id: Option<&'s str>,
+    pub summary: Option<&'s str>,
+    item: &'s Value,
+    op: &'s Value,
+}
+
+impl<'s> Operation<'s> {
+    /// The operationId if there is one, otherwise the method and path.
+    pub fn name(&self) -> String {
+        match self.id {
+            Some(id) => id.to_owned(),
+            None => format!("{} {}", self.method.to_uppercase(), self.path),
+        }
+    }
+}
+
+impl Spec {
+    /// Reads a spec written in YAML if the file ends in `.yaml` or `.yml`, and in JSON otherwise.
+    pub fn load(path: &Path) -> HurlResult<Spec> {
+        let content = fs::read_to_string(path).map_err(|source| Error::File {
+            path: path.to_owned(),
+            access: Access::Read,
+            source,
+        })?;
+        let is_yaml = matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("yaml") | Some("yml")
+        );
+        let parse_error = |message: String| Error::SpecParse {
+            path: path.to_owned(),
+            message,
+        };
+        let doc: Value = if is_yaml {
+            serde_yaml::from_str(&content).map_err(|e| parse_error(e.to_string()))?
+        } else {
+            serde_json::from_str(&content).map_err(|e| parse_error(e.to_string()))?
+        };
+        match doc.get("openapi").and_then(Value::as_str) {
+            Some(version) if version.starts_with('3') => Ok(Spec { doc }),
+            Some(version) => Err(parse_error(format!(
+                "OpenAPI {} is not supported, only version 3",
+                version
+            ))),
+            None => Err(parse_error(
+                "missing the `openapi` version field".to_owned(),
+            )),
+        }
+    }
+
+    pub fn operations(&self) -> Vec<Operation<'_>> {
+        let mut operations = Vec::new();
+        let paths = match self.doc.get("paths").and_then(Value::as_object) {
+            Some(paths) => paths,
+            None => return operations,
+        };
+        for (path, item) in paths.iter() {
+            let item = self.resolve(item);
+            for method in METHODS.iter() {
+                if let Some(op) = item.get(*method) {
+                    operations.push(Operation {
+                        method,
+                        path,
+                        id: op.get("operationId").and_then(Value::as_str),
+                        summary: op.get("summary").and_then(Value::as_str),
+                        item,
+                        op,
+                    });
+                }
+            }
+        }
+        operations
+    }
+
+    /// Validates the parameters and JSON body of a request against its operation.
+    ///
+    /// Requests which do not match any operation in the spec are sent as they are.
+    pub fn check_request(&self, request: &Request, payload: &Payload) -> HurlResult<()> {
+        let (op, path_params) = match self.find(request.method(), request.url()) {
+            Some(found) => found,
+            None => {
+                warn!(
+                    "No operation in the OpenAPI spec matches {} {}",
+                    request.method(),
+                    request.url().path()
+                );
+                return Ok(());
+            }
+        };
+        debug!("Validating request against operation {}", op.name());
+
+        let mut violations = Vec::new();
+        let query: Vec<(String, String)> = request.url().query_pairs().into_owned().collect();
+        let parameters = self.parameters(&op);
+        for param in parameters.iter() {
+            let name = match param.get("name").and_then(Value::as_str) {
+                Some(name) => name,
+                None => continue,
+            };
+            let location = param.get("in").and_then(Value::as_str).unwrap_or("");
+            let values: Vec<&str> = match location {
+                "query" => query
+                    .iter()
+                    .filter(|(k, _)| k == name)
+                    .map(|(_, v)| v.as_str())
+                    .collect(),
+                "header" => request
+                    .headers()
+                    .get_all(name)
+                    .iter()
+                    .filter_map(|v| v.to_str().ok())
+                    .collect(),
+                "path" => path_params
+                    .iter()
+                    .filter(|(k, _)| *k == name)
+                    .map(|(_, v)| v.as_str())
+                    .collect(),
+                _ => continue,
+            };
+            let label = format!("{} parameter `{}`", location, name);
+            if values.is_empty() {
+                if param.get("required") == Some(&Value::Bool(true)) {
+                    violations.push(Violation {
+                        location: label,
+                        message: "is required".to_owned(),
+                    });
+                }
+                continue;
+            }
+            if let Some(s) = param.get("schema") {
+                let value = self.typed_parameter(s, &values);
+                for mut v in schema::validate(&self.doc, s, &value) {
+                    v.location = format!("{}{}", label, v.location);
+                    violations.push(v);
+                }
+            }
+        }
+        for (key, _) in query.iter() {
+            let declared = parameters.iter().any(|p| {
+                p.get("in").and_then(Value::as_str) == Some("query")
+                    && p.get("name").and_then(Value::as_str) == Some(key)
+            });
+            if !declared {
+                violations.push(Violation {
+                    location: format!("query parameter `{}`", key),
+                    message: "is not declared by the operation".to_owned(),
+                });
+            }
+        }
+
+        if let Some(body) = op.op.get("requestBody").map(|b| self.resolve(b)) {
+            match payload {
+                Payload::Empty => {
+                    if body.get("required") == Some(&Value::Bool(true)) {
+                        violations.push(Violation {
+                            location: "body".to_owned(),
+                            message: "is required".to_owned(),
+                        });
+                    }
+                }
+                Payload::Bytes {
+                    content_type: "application/json",
+                    bytes,
+                } => {
+                    if let Some(s) = json_schema(body) {
+                        let value: Value = serde_json::from_slice(bytes)?;
+                        violations.extend(self.validate_body(s, &value));
+                    }
+                }
+                _ => trace!("Only JSON request bodies are validated"),
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidRequest {
+                operation: op.name(),
+                violations,
+            })
+        }
+    }
+
+    /// Validates the status and JSON body of a response against the declared responses of its
+    /// operation.
+    pub fn check_response(
+        &self,
+        method: &Method,
+        url: &Url,
+        resp: &Response,
+        body: &str,
+    ) -> HurlResult<()> {
+        let (op, _) = match self.find(method, url) {
+            Some(found) => found,
+            None => return Ok(()),
+        };
+        let responses = match op.op.get("responses").and_then(Value::as_object) {
+            Some(responses) => responses,
+            None => return Ok(()),
+        };
+        let status = resp.status().as_u16().to_string();
+        let range = format!("{}XX", &status[..1]);
+        let declared = responses
+            .get(&status)
+            .or_else(|| responses.get(&range))
+            .or_else(|| responses.get(&range.to_lowercase()))
+            .or_else(|| responses.get("default"))
+            .map(|r| self.resolve(r));
+
+        let mut violations = Vec::new();
+        match declared {
+            None => violations.push(Violation {
+                location: "status".to_owned(),
+                message: format!("{} is not declared by the operation", status),
+            }),
+            Some(declared) => {
+                let is_json = resp
+                    .headers()
+                    .get(reqwest::header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .filter(|v| is_json_media_type(v))
+                    .is_some();
+                if let (true, Some(s)) = (is_json, json_schema(declared)) {
+                    match serde_json::from_str::<Value>(body) {
+                        Ok(value) => violations.extend(self.validate_body(s, &value)),
+                        Err(e) => violations.push(Violation {
+                            location: "body".to_owned(),
+                            message: format!("is not valid JSON: {}", e),
+                        }),
+                    }
+                }
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidResponse {
+                operation: op.name(),
+                violations,
+            })
+        }
+    }
+
+    /// Finds the operation for a request along with the values of its path parameters.
+    ///
+    /// The path of the first server URL which is a prefix of the request path, ending at a
+    /// segment boundary, is removed before matching. Paths with fewer templated segments win,
+    /// so `/pets/mine` is preferred over `/pets/{id}`.
+    fn find(&self, method: &Method, url: &Url) -> Option<(Operation<'_>, Vec<(String, String)>)> {
+        let path = self
+            .base_paths()
+            .iter()
+            .filter_map(|base| {
+                let rest = url.path().strip_prefix(base.as_str())?;
+                if rest.is_empty() || rest.starts_with('/') {
+                    Some(rest)
+                } else {
+                    None
+                }
+            })
+            .next()
+            .unwrap_or_else(|| url.path());
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        let method = method.as_str().to_lowercase();
+        self.operations()
+            .into_iter()
+            .filter(|op| op.method == method)
+            .filter_map(|op| {
+                let template: Vec<&str> = op.path.trim_matches('/').split('/').collect();
+                if template.len() != segments.len() {
+                    return None;
+                }
+                let mut params = Vec::new();
+                for (t, s) in template.iter().zip(segments.iter()) {
+                    if t.starts_with('{') && t.ends_with('}') && !s.is_empty() {
+                        params.push((t[1..t.len() - 1].to_owned(), (*s).to_owned()));
+                    } else if t != s {
+                        return None;
+                    }
+                }
+                Some((op, params))
+            })
+            .min_by_key(|(_, params)| params.len())
+    }
+
+    /// The paths of the server URLs, without a trailing slash, longest first.
+    fn base_paths(&self) -> Vec<String> {
+        let servers = self.doc.get("servers").and_then(Value::as_array);
+        let mut paths: Vec<String> = servers
+            .into_iter()
+            .flatten()
+            .filter_map(|s| s.get("url").and_then(Value::as_str))
+            .map(|url| {
+                let path = match url.find("://") {
+                    Some(i) => url[i + 3..].find('/').map_or("", |j| &url[i + 3 + j..]),
+                    None => url,
+                };
+                path.trim_end_matches('/').to_owned()
+            })
+            .filter(|path| !path.is_empty())
+            .collect();
+        paths.sort_by_key(|path| std::cmp::Reverse(path.len()));
+        paths
+    }
+
+    /// The parameters of an operation, including those shared by every operation on its path
+    /// unless the operation overrides them.
+    fn parameters<'o>(&'o self, op: &Operation<'o>) -> Vec<&'o Value> {
+        let list = |v: &'o Value| -> Vec<&'o Value> {
+            v.get("parameters")
+                .and_then(Value::as_array)
+                .map(|ps| ps.iter().map(|p| self.resolve(p)).collect())
+                .unwrap_or_default()
+        };
+        let own = list(op.op);
+        let mut parameters: Vec<&Value> = list(op.item)
+            .into_iter()
+            .filter(|shared| {
+                !own.iter()
+                    .any(|p| p.get("name") == shared.get("name") && p.get("in") == shared.get("in"))
+            })
+            .collect();
+        parameters.extend(own);
+        parameters
+    }
+
+    /// Converts parameter strings to the type their schema expects so that `limit=10` is
+    /// checked as the number 10.
+    fn typed_parameter(&self, s: &Value, values: &[&str]) -> Value {
+        let s = self.resolve(s);
+        match s.get("type").and_then(Value::as_str) {
+            Some("array") => {
+                let items = s.get("items").unwrap_or(&Value::Null);
+                let values: Vec<&str> = if values.len() == 1 {
+                    values[0].split(',').collect()
+                } else {
+                    values.to_vec()
+                };
+                Value::Array(
+                    values
+                        .iter()
+                        .map(|v| self.typed_parameter(items, &[v]))
+                        .collect(),
+                )
+            }
+            Some("integer") | Some("number") | Some("boolean") => {
+                serde_json::from_str(values[0]).unwrap_or_else(|_| text(values[0]))
+            }
+            _ => text(values[0]),
+        }
+    }
+
+    fn validate_body(&self, s: &Value, value: &Value) -> Vec<Violation> {
+        let mut violations = schema::validate(&self.doc, s, value);
+        for v in violations.iter_mut() {
+            v.location = format!("body{}", v.location);
+        }
+        violations
+    }
+
+    /// Follows a `$ref` to a component, or returns the value itself if it is not a reference.
+    fn resolve<'v>(&'v self, value: &'v Value) -> &'v Value {
+        match value.get("$ref").and_then(Value::as_str) {
+            Some(reference) => schema::resolve_ref(&self.doc, reference).unwrap_or(value),
+            None => value,
+        }
+    }
+}
+
+/// The schema of the JSON content of a request body or response.
+fn json_schema(declared: &Value) -> Option<&Value> {
+    declared
+        .get("content")
+        .and_then(Value::as_object)?
+        .iter()
+        .find(|(media_type, _)| is_json_media_type(media_type))
+        .and_then(|(_, media)| media.get("schema"))
+}
+
+fn is_json_media_type(media_type: &str) -> bool {
+    let essence = media_type.split(';').next().unwrap_or("").trim();
+    essence == "application/json" || essence.ends_with("+json")
+}
+
+fn text(s: &str) -> Value {
+    Value::String(s.to_owned())
+}
@@ -0,0 +1,125 @@
+use crate::app::App;
+use crate::errors::Error;
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+/// The upper bound on how long we will wait between two attempts, regardless of how many
+/// attempts have been made or what the server asks for in `Retry-After`.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// The conditions used when `--retry` is given without `--retry-on`.
+const DEFAULT_CONDITIONS: [RetryCondition; 5] = [
+    RetryCondition::Status(429),
+    RetryCondition::Status(502),
+    RetryCondition::Status(503),
+    RetryCondition::Status(504),
+    RetryCondition::Timeout,
+];
+
+/// A failure which is considered transient and therefore worth another attempt.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetryCondition {
+    /// The server responded with this status code.
+    Status(u16),
+    /// The request timed out.
+    Timeout,
+    /// The request failed without a response for any other reason, e.g. the connection
+    /// was refused or reset.
+    Error,
+}
+
+impl FromStr for RetryCondition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "timeout" => Ok(RetryCondition::Timeout),
+            "error" => Ok(RetryCondition::Error),
+            other => other
+                .parse::<u16>()
+                .ok()
+                .filter(|code| StatusCode::from_u16(*code).is_ok())
+                .map(RetryCondition::Status)
+                .ok_or_else(|| Error::InvalidRetryCondition(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RetryPolicy {
+    retries: u32,
+    base_delay: Duration,
+    conditions: Vec<RetryCondition>,
+}
+
+impl RetryPolicy {
+    pub fn from_app(app: &App) -> Self {
+        let conditions = if app.retry_on.is_empty() {
+            DEFAULT_CONDITIONS.to_vec()
+        } else {
+            app.retry_on.clone()
+        };
+        RetryPolicy {
+            retries: app.retry,
+            base_delay: Duration::from_millis(app.retry_delay),
+            conditions,
+        }
+    }
+
+    /// The number of attempts after the first one that we are allowed to make.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    pub fn should_retry_response(&self, resp: &Response) -> bool {
+        let code = resp.status().as_u16();
+        self.conditions.contains(&RetryCondition::Status(code))
+    }
+
+    pub fn should_retry_error(&self, err: &reqwest::Error) -> bool {
+        if err.is_timeout() {
+            self.conditions.contains(&RetryCondition::Timeout)
+        } else if let Some(status) = err.status() {
+            self.conditions
+                .contains(&RetryCondition::Status(status.as_u16()))
+        } else {
+            !err.is_serialization() && self.conditions.contains(&RetryCondition::Error)
+        }
+    }
+
+    /// How long to wait before making the attempt after `attempt`.
+    ///
+    /// A `Retry-After` header on the failed response takes precedence. Otherwise the delay
+    /// doubles with each attempt and half of it is randomized so that many clients failing
+    /// at once do not retry in lockstep.
+    pub fn delay(&self, attempt: u32, resp: Option<&Response>) -> Duration {
+        if let Some(delay) = resp.and_then(retry_after) {
+            return delay.min(MAX_DELAY);
+        }
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let backoff = self
+            .base_delay
+            .checked_mul(factor)
+            .unwrap_or(MAX_DELAY)
+            .min(MAX_DELAY);
+        let half = backoff / 2;
+        let jitter_ms = rand::thread_rng().gen_range(0, half.as_millis() as u64 + 1);
+        half + Duration::from_millis(jitter_ms)
+    }
+}
+
+/// Parses a `Retry-After` header, which is either a number of seconds or an HTTP date.
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let value = resp.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or_else(|_| Duration::from_secs(0)),
+    )
+}
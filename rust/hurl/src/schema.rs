@@ -0,0 +1,556 @@
+use crate::errors::{Access, Error, HurlResult};
+use chrono::{DateTime, NaiveDate};
+use log::trace;
+use regex::Regex;
+use reqwest::Url;
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+/// How many `$ref`s may be followed without moving on to a nested value before we assume they
+/// refer to each other in a loop. Recursion through nested values ends with the value itself.
+const MAX_REFS: usize = 64;
+
+/// A single way in which a value does not match a schema.
+#[derive(Debug)]
+pub struct Violation {
+    /// Where the offending value is, e.g. a JSON pointer into a body.
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let location = if self.location.is_empty() {
+            "/"
+        } else {
+            &self.location
+        };
+        write!(f, "{}: {}", location, self.message)
+    }
+}
+
+/// Reads a standalone JSON Schema document, as given with --schema.
+pub fn load(path: &Path) -> HurlResult<Value> {
+    let content = fs::read_to_string(path).map_err(|source| Error::File {
+        path: path.to_owned(),
+        access: Access::Read,
+        source,
+    })?;
+    let schema: Value = serde_json::from_str(&content).map_err(|e| Error::SchemaParse {
+        path: path.to_owned(),
+        message: e.to_string(),
+    })?;
+    match schema {
+        Value::Object(_) | Value::Bool(_) => Ok(schema),
+        _ => Err(Error::SchemaParse {
+            path: path.to_owned(),
+            message: "a schema must be an object or a boolean".to_owned(),
+        }),
+    }
+}
+
+/// Validates `value` against `schema`, returning every violation found.
+///
+/// `root` is the document that local `$ref`s such as `#/components/schemas/Pet` are resolved
+/// against, which for a standalone schema is the schema itself. Violations are located by JSON
+/// pointers into `value`.
+///
+/// Both JSON Schema draft-07 and the OpenAPI 3.0 dialect are understood: `nullable` and boolean
+/// `exclusiveMinimum`/`exclusiveMaximum` are accepted alongside their JSON Schema forms.
+pub fn validate(root: &Value, schema: &Value, value: &Value) -> Vec<Violation> {
+    let mut validator = Validator {
+        root,
+        violations: Vec::new(),
+    };
+    validator.check(schema, value, "", 0);
+    validator.violations
+}
+
+struct Validator<'a> {
+    root: &'a Value,
+    violations: Vec<Violation>,
+}
+
+impl<'a> Validator<'a> {
+    fn fail(&mut self, pointer: &str, message: String) {
+        self.violations.push(Violation {
+            location: pointer.to_owned(),
+            message,
+        });
+    }
+
+    /// Whether `value` matches `schema`, without recording any violations.
+    fn matches(&self, schema: &Value, value: &Value, refs: usize) -> bool {
+        let mut nested = Validator {
+            root: self.root,
+            violations: Vec::new(),
+        };
+        nested.check(schema, value, "", refs);
+        nested.violations.is_empty()
+    }
+
+    /// `refs` counts the `$ref`s followed since `value` was reached, and starts again from zero
+    /// for each nested value.
+    fn check(&mut self, schema: &Value, value: &Value, pointer: &str, refs: usize) {
+        if refs > MAX_REFS {
+            self.fail(pointer, "schema refers to itself in a loop".to_owned());
+            return;
+        }
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => {
+                self.fail(pointer, "no value is allowed here".to_owned());
+                return;
+            }
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            match resolve_ref(self.root, reference) {
+                Some(target) => self.check(target, value, pointer, refs + 1),
+                None => trace!("Skipping unresolvable $ref {}", reference),
+            }
+            return;
+        }
+
+        if value.is_null() && schema.get("nullable") == Some(&Value::Bool(true)) {
+            return;
+        }
+
+        if let Some(types) = schema.get("type") {
+            let allowed: Vec<&str> = match types {
+                Value::String(t) => vec![t.as_str()],
+                Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            if !allowed.is_empty() && !allowed.iter().any(|t| is_type(value, t)) {
+                self.fail(
+                    pointer,
+                    format!(
+                        "expected {}, found {}",
+                        allowed.join(" or "),
+                        type_name(value)
+                    ),
+                );
+                return;
+            }
+        }
+
+        if let Some(Value::Array(options)) = schema.get("enum") {
+            if !options.contains(value) {
+                self.fail(
+                    pointer,
+                    format!("{} is not one of {}", value, Value::Array(options.clone())),
+                );
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if expected != value {
+                self.fail(pointer, format!("expected {}, found {}", expected, value));
+            }
+        }
+
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::Array(required)) = schema.get("required") {
+                    for name in required.iter().filter_map(Value::as_str) {
+                        if !map.contains_key(name) {
+                            self.fail(pointer, format!("missing required property `{}`", name));
+                        }
+                    }
+                }
+                let properties = schema.get("properties").and_then(Value::as_object);
+                let patterns: Vec<(Regex, &Value)> = schema
+                    .get("patternProperties")
+                    .and_then(Value::as_object)
+                    .map(|p| {
+                        p.iter()
+                            .filter_map(|(pattern, s)| Regex::new(pattern).ok().map(|re| (re, s)))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                for (key, v) in map.iter() {
+                    let child = format!("{}/{}", pointer, escape(key));
+                    let mut matched = false;
+                    if let Some(s) = properties.and_then(|p| p.get(key)) {
+                        self.check(s, v, &child, 0);
+                        matched = true;
+                    }
+                    for (_, s) in patterns.iter().filter(|(re, _)| re.is_match(key)) {
+                        self.check(s, v, &child, 0);
+                        matched = true;
+                    }
+                    if !matched {
+                        match schema.get("additionalProperties") {
+                            Some(Value::Bool(false)) => {
+                                self.fail(pointer, format!("unknown property `{}`", key))
+                            }
+                            Some(s @ Value::Object(_)) => self.check(s, v, &child, 0),
+                            _ => {}
+                        }
+                    }
+                    if let Some(names) = schema.get("propertyNames") {
+                        if !self.matches(names, &Value::String(key.clone()), 0) {
+                            self.fail(
+                                pointer,
+                                format!("property name `{}` does not match propertyNames", key),
+                            );
+                        }
+                    }
+                }
+                if let Some(Value::Object(dependencies)) = schema.get("dependencies") {
+                    for (name, dependency) in dependencies.iter() {
+                        if !map.contains_key(name) {
+                            continue;
+                        }
+                        match dependency {
+                            Value::Array(needed) => {
+                                for other in needed.iter().filter_map(Value::as_str) {
+                                    if !map.contains_key(other) {
+                                        self.fail(
+                                            pointer,
+                                            format!(
+                                                "property `{}` requires property `{}`",
+                                                name, other
+                                            ),
+                                        );
+                                    }
+                                }
+                            }
+                            s => self.check(s, value, pointer, refs),
+                        }
+                    }
+                }
+                self.check_len(
+                    schema,
+                    "minProperties",
+                    "maxProperties",
+                    map.len(),
+                    "properties",
+                    pointer,
+                );
+            }
+            Value::Array(items) => {
+                match schema.get("items") {
+                    Some(Value::Array(tuple)) => {
+                        for (i, (s, v)) in tuple.iter().zip(items.iter()).enumerate() {
+                            self.check(s, v, &format!("{}/{}", pointer, i), 0);
+                        }
+                        match schema.get("additionalItems") {
+                            Some(Value::Bool(false)) if items.len() > tuple.len() => self.fail(
+                                pointer,
+                                format!(
+                                    "has {} items, expected at most {}",
+                                    items.len(),
+                                    tuple.len()
+                                ),
+                            ),
+                            Some(s @ Value::Object(_)) => {
+                                for (i, v) in items.iter().enumerate().skip(tuple.len()) {
+                                    self.check(s, v, &format!("{}/{}", pointer, i), 0);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    Some(s) => {
+                        for (i, v) in items.iter().enumerate() {
+                            self.check(s, v, &format!("{}/{}", pointer, i), 0);
+                        }
+                    }
+                    None => {}
+                }
+                self.check_len(
+                    schema,
+                    "minItems",
+                    "maxItems",
+                    items.len(),
+                    "items",
+                    pointer,
+                );
+                if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+                    let duplicate = items
+                        .iter()
+                        .enumerate()
+                        .any(|(i, v)| items[..i].contains(v));
+                    if duplicate {
+                        self.fail(pointer, "items are not unique".to_owned());
+                    }
+                }
+                if let Some(contains) = schema.get("contains") {
+                    if !items.iter().any(|v| self.matches(contains, v, 0)) {
+                        self.fail(pointer, "no item matches the contains schema".to_owned());
+                    }
+                }
+            }
+            Value::String(s) => {
+                let len = s.chars().count();
+                self.check_len(schema, "minLength", "maxLength", len, "characters", pointer);
+                if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+                    match Regex::new(pattern) {
+                        Ok(re) if !re.is_match(s) => {
+                            self.fail(pointer, format!("does not match pattern `{}`", pattern))
+                        }
+                        Ok(_) => {}
+                        Err(_) => trace!("Skipping invalid pattern {}", pattern),
+                    }
+                }
+                if let Some(format) = schema.get("format").and_then(Value::as_str) {
+                    if !is_format(s, format) {
+                        self.fail(pointer, format!("`{}` is not a valid {}", s, format));
+                    }
+                }
+            }
+            Value::Number(n) => {
+                if let Some(n) = n.as_f64() {
+                    self.check_number(schema, n, pointer);
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(Value::Array(all)) = schema.get("allOf") {
+            for s in all.iter() {
+                self.check(s, value, pointer, refs);
+            }
+        }
+        if let Some(condition) = schema.get("if") {
+            let branch = if self.matches(condition, value, refs) {
+                schema.get("then")
+            } else {
+                schema.get("else")
+            };
+            if let Some(s) = branch {
+                self.check(s, value, pointer, refs);
+            }
+        }
+        if let Some(Value::Array(any)) = schema.get("anyOf") {
+            if !any.iter().any(|s| self.matches(s, value, refs)) {
+                self.fail(pointer, "does not match any schema in anyOf".to_owned());
+            }
+        }
+        if let Some(Value::Array(one)) = schema.get("oneOf") {
+            let n = one.iter().filter(|s| self.matches(s, value, refs)).count();
+            if n != 1 {
+                self.fail(
+                    pointer,
+                    format!("matches {} schemas in oneOf, expected exactly 1", n),
+                );
+            }
+        }
+        if let Some(not) = schema.get("not") {
+            if self.matches(not, value, refs) {
+                self.fail(pointer, "matches a schema in not".to_owned());
+            }
+        }
+    }
+
+    fn check_len(
+        &mut self,
+        schema: &serde_json::Map<String, Value>,
+        min_key: &str,
+        max_key: &str,
+        len: usize,
+        what: &str,
+        pointer: &str,
+    ) {
+        if let Some(min) = schema.get(min_key).and_then(Value::as_u64) {
+            if (len as u64) < min {
+                self.fail(
+                    pointer,
+                    format!("has {} {}, expected at least {}", len, what, min),
+                );
+            }
+        }
+        if let Some(max) = schema.get(max_key).and_then(Value::as_u64) {
+            if (len as u64) > max {
+                self.fail(
+                    pointer,
+                    format!("has {} {}, expected at most {}", len, what, max),
+                );
+            }
+        }
+    }
+
+    fn check_number(&mut self, schema: &serde_json::Map<String, Value>, n: f64, pointer: &str) {
+        // OpenAPI 3.0 uses booleans to make `minimum` and `maximum` exclusive, while JSON
+        // Schema draft-06 and later give the bound itself.
+        let exclusive = |key: &str| schema.get(key) == Some(&Value::Bool(true));
+        if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+            if n < min || (exclusive("exclusiveMinimum") && n == min) {
+                self.fail(pointer, format!("{} is less than the minimum {}", n, min));
+            }
+        }
+        if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+            if n > max || (exclusive("exclusiveMaximum") && n == max) {
+                self.fail(
+                    pointer,
+                    format!("{} is greater than the maximum {}", n, max),
+                );
+            }
+        }
+        if let Some(min) = schema.get("exclusiveMinimum").and_then(Value::as_f64) {
+            if n <= min {
+                self.fail(pointer, format!("{} is not greater than {}", n, min));
+            }
+        }
+        if let Some(max) = schema.get("exclusiveMaximum").and_then(Value::as_f64) {
+            if n >= max {
+                self.fail(pointer, format!("{} is not less than {}", n, max));
+            }
+        }
+        if let Some(m) = schema.get("multipleOf").and_then(Value::as_f64) {
+            // The quotient of two decimals is rarely exact in binary, so it only has to be
+            // close to a whole number relative to its own size.
+            let q = n / m;
+            if m > 0.0 && (q - q.round()).abs() > 1e-9 * q.abs().max(1.0) {
+                self.fail(pointer, format!("{} is not a multiple of {}", n, m));
+            }
+        }
+    }
+}
+
+/// Resolves a local reference such as `#/components/schemas/Pet` within `root`.
+pub fn resolve_ref<'v>(root: &'v Value, reference: &str) -> Option<&'v Value> {
+    root.pointer(reference.strip_prefix('#')?)
+}
+
+fn is_type(value: &Value, t: &str) -> bool {
+    match t {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => match value {
+            Value::Number(n) => {
+                n.is_i64() || n.is_u64() || n.as_f64().filter(|f| f.fract() == 0.0).is_some()
+            }
+            _ => false,
+        },
+        _ => true,
+    }
+}
+
+/// Whether `s` is valid for a `format`. Formats we do not know are always valid, since the
+/// specification makes them annotations that validators may ignore.
+fn is_format(s: &str, format: &str) -> bool {
+    match format {
+        "date-time" => DateTime::parse_from_rfc3339(s).is_ok(),
+        "date" => NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok(),
+        "time" => DateTime::parse_from_rfc3339(&format!("1970-01-01T{}", s)).is_ok(),
+        "email" => match s.rfind('@') {
+            Some(at) => at > 0 && is_hostname(&s[at + 1..]),
+            None => false,
+        },
+        "hostname" => is_hostname(s),
+        "ipv4" => s.parse::<Ipv4Addr>().is_ok(),
+        "ipv6" => s.parse::<Ipv6Addr>().is_ok(),
+        "uri" => Url::parse(s).is_ok(),
+        "uuid" => {
+            s.len() == 36
+                && s.char_indices().all(|(i, c)| match i {
+                    8 | 13 | 18 | 23 => c == '-',
+                    _ => c.is_ascii_hexdigit(),
+                })
+        }
+        "regex" => Regex::new(s).is_ok(),
+        _ => true,
+    }
+}
+
+fn is_hostname(s: &str) -> bool {
+    let s = s.strip_suffix('.').unwrap_or(s);
+    !s.is_empty()
+        && s.len() <= 253
+        && s.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+pub fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Escapes a key for use in a JSON pointer as described in RFC 6901.
+pub fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn violations(schema: Value, value: Value) -> Vec<Violation> {
+        validate(&schema, &schema, &value)
+    }
+
+    #[test]
+    fn multiple_of_allows_decimal_rounding_error() {
+        assert!(violations(json!({"multipleOf": 0.1}), json!(0.3)).is_empty());
+        assert!(violations(json!({"multipleOf": 2.5}), json!(7.5)).is_empty());
+        assert!(violations(json!({"multipleOf": 0.01}), json!(19.99)).is_empty());
+    }
+
+    #[test]
+    fn multiple_of_rejects_other_numbers() {
+        let found = violations(json!({"multipleOf": 0.1}), json!(0.35));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].message, "0.35 is not a multiple of 0.1");
+        assert_eq!(violations(json!({"multipleOf": 3}), json!(10)).len(), 1);
+    }
+
+    fn tree(levels: usize) -> Value {
+        (0..levels).fold(
+            json!({"children": []}),
+            |child, _| json!({"children": [child]}),
+        )
+    }
+
+    #[test]
+    fn recursive_refs_follow_deep_values() {
+        let schema = json!({
+            "type": "object",
+            "required": ["children"],
+            "properties": {"children": {"type": "array", "items": {"$ref": "#"}}}
+        });
+        assert!(violations(schema.clone(), tree(100)).is_empty());
+
+        let mut broken = tree(100);
+        *broken.pointer_mut(&"/children/0".repeat(100)).unwrap() = json!({});
+        let found = violations(schema, broken);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].location, "/children/0".repeat(100));
+    }
+
+    #[test]
+    fn refs_in_a_loop_fail() {
+        let schema = json!({
+            "definitions": {
+                "a": {"$ref": "#/definitions/b"},
+                "b": {"$ref": "#/definitions/a"}
+            },
+            "$ref": "#/definitions/a"
+        });
+        let found = violations(schema, json!(1));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].message, "schema refers to itself in a loop");
+    }
+}
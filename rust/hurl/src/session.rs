@@ -1,12 +1,13 @@
 use crate::app::{App, Parameter};
+use crate::cache::CacheEntry;
 use crate::directories::DIRECTORIES;
-use crate::errors::HurlResult;
+use crate::errors::{Access, Error, HurlResult};
 use reqwest::header::COOKIE;
 use reqwest::RequestBuilder;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::{create_dir_all, File, OpenOptions};
-use std::io::{BufReader, BufWriter};
+use std::io::{BufReader, BufWriter, ErrorKind};
 use std::path::PathBuf;
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -18,6 +19,8 @@ pub struct Session {
     token: Option<String>,
     headers: HashMap<String, String>,
     cookies: Vec<(String, String)>,
+    #[serde(default)]
+    cache: HashMap<String, CacheEntry>,
 }
 
 impl Session {
@@ -33,15 +36,28 @@ impl Session {
 
     pub fn load(app: &App, name: &str, host: &str) -> HurlResult<Self> {
         let path = Session::path(app, name, host);
-        let file = File::open(path)?;
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(source) => {
+                return Err(Error::File {
+                    path,
+                    access: Access::Read,
+                    source,
+                })
+            }
+        };
         let reader = BufReader::new(file);
-        serde_json::from_reader(reader).map_err(|e| e.into())
+        serde_json::from_reader(reader).map_err(|source| Error::SessionParse { path, source })
     }
 
-    pub fn get_or_create(app: &App, name: String, host: String) -> Self {
+    /// Loads the named session, or starts a new one if it has not been saved before.
+    pub fn get_or_create(app: &App, name: String, host: String) -> HurlResult<Self> {
         match Session::load(app, &name, &host) {
-            Ok(session) => session,
-            Err(_) => Session::new(app, name, host),
+            Ok(session) => Ok(session),
+            Err(Error::File { ref source, .. }) if source.kind() == ErrorKind::NotFound => {
+                Ok(Session::new(app, name, host))
+            }
+            Err(e) => Err(e),
         }
     }
 
@@ -66,12 +82,21 @@ impl Session {
 
     pub fn save(&self, app: &App) -> HurlResult<()> {
         let dir = Session::dir(app, &self.host);
-        create_dir_all(dir)?;
+        create_dir_all(&dir).map_err(|source| Error::File {
+            path: dir,
+            access: Access::Write,
+            source,
+        })?;
         let file = OpenOptions::new()
             .create(true)
             .write(true)
             .truncate(true)
-            .open(&self.path)?;
+            .open(&self.path)
+            .map_err(|source| Error::File {
+                path: self.path.clone(),
+                access: Access::Write,
+                source,
+            })?;
         let writer = BufWriter::new(file);
         serde_json::to_writer(writer, &self).map_err(|e| e.into())
     }
@@ -91,6 +116,12 @@ impl Session {
         }
     }
 
+    pub fn has_header(&self, name: &str) -> bool {
+        self.headers
+            .keys()
+            .any(|key| key.eq_ignore_ascii_case(name))
+    }
+
     pub fn update_auth(&mut self, auth: &Option<String>, token: &Option<String>) {
         if auth.is_some() {
             self.auth = auth.clone();
@@ -123,6 +154,14 @@ impl Session {
                 .push((cookie.name().to_owned(), cookie.value().to_owned()));
         }
     }
+
+    pub fn cached(&self, key: &str) -> Option<&CacheEntry> {
+        self.cache.get(key)
+    }
+
+    pub fn update_cache(&mut self, key: String, entry: CacheEntry) {
+        self.cache.insert(key, entry);
+    }
 }
 
 pub fn make_safe_pathname(s: &str) -> String {
@@ -0,0 +1,481 @@
+use crate::client::Payload;
+use crate::errors::{Error, HurlResult};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use log::{trace, warn};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, DATE, HOST};
+use reqwest::{Method, Request, Url};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The payload hash AWS expects when the body cannot be read before it is sent.
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// The signer section of a profile in the configuration file, e.g.
+///
+/// ```toml
+/// [profiles.minio.signer]
+/// type = "aws-sigv4"
+/// access_key = "minio"
+/// secret_key = "minio123"
+/// region = "us-east-1"
+/// service = "s3"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum SignerConfig {
+    Hmac {
+        key_id: String,
+        secret: String,
+        /// The header which carries the signature.
+        #[serde(default = "default_hmac_header")]
+        header: String,
+    },
+    AwsSigv4 {
+        access_key: String,
+        secret_key: String,
+        session_token: Option<String>,
+        region: String,
+        service: String,
+    },
+}
+
+fn default_hmac_header() -> String {
+    AUTHORIZATION.as_str().to_owned()
+}
+
+/// A stage which adds headers computed from the final request, after every other header and
+/// the body have been set.
+pub trait Signer {
+    fn sign(&self, request: &mut Request, payload: &Payload) -> HurlResult<()>;
+}
+
+pub fn from_config(config: &SignerConfig) -> Box<dyn Signer + '_> {
+    match config {
+        SignerConfig::Hmac {
+            key_id,
+            secret,
+            header,
+        } => Box::new(HmacSigner {
+            key_id,
+            secret,
+            header,
+        }),
+        SignerConfig::AwsSigv4 {
+            access_key,
+            secret_key,
+            session_token,
+            region,
+            service,
+        } => Box::new(SigV4Signer {
+            access_key,
+            secret_key,
+            session_token: session_token.as_ref().map(String::as_str),
+            region,
+            service,
+        }),
+    }
+}
+
+/// Signs the method, path with query, date and a hash of the body with a shared secret.
+///
+/// The string to sign is those four values joined by newlines. The request gets a `Date` header
+/// if it did not have one, an `X-Content-SHA256` header with the body hash, and the signature as
+/// `HMAC-SHA256 KeyId=<key_id>, Signature=<hex>` in the configured header.
+struct HmacSigner<'a> {
+    key_id: &'a str,
+    secret: &'a str,
+    header: &'a str,
+}
+
+impl<'a> Signer for HmacSigner<'a> {
+    fn sign(&self, request: &mut Request, payload: &Payload) -> HurlResult<()> {
+        let body =
+            match payload {
+                Payload::Empty => &[][..],
+                Payload::Bytes { bytes, .. } => &bytes[..],
+                Payload::Streamed => return Err(Error::Signing(
+                    "HMAC signatures need the whole body, which is not available for multipart \
+                     uploads"
+                        .to_owned(),
+                )),
+            };
+        let body_hash = hex::encode(Sha256::digest(body));
+
+        let date = match request.headers().get(DATE) {
+            Some(date) => header_str(date)?.to_owned(),
+            None => {
+                let date = httpdate::fmt_http_date(std::time::SystemTime::now());
+                request.headers_mut().insert(DATE, header_value(&date)?);
+                date
+            }
+        };
+
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{}",
+            request.method(),
+            path_and_query(request.url()),
+            date,
+            body_hash
+        );
+        trace!("HMAC string to sign: {:?}", string_to_sign);
+        let signature = hex::encode(hmac(self.secret.as_bytes(), string_to_sign.as_bytes()));
+
+        let name = HeaderName::from_bytes(self.header.as_bytes())
+            .map_err(|_| Error::Signing(format!("invalid header name '{}'", self.header)))?;
+        if request.headers().contains_key(&name) {
+            warn!("Replacing the {} header with the HMAC signature", name);
+        }
+        let headers = request.headers_mut();
+        headers.insert("x-content-sha256", header_value(&body_hash)?);
+        headers.insert(
+            name,
+            header_value(&format!(
+                "HMAC-SHA256 KeyId={}, Signature={}",
+                self.key_id, signature
+            ))?,
+        );
+        Ok(())
+    }
+}
+
+/// AWS Signature Version 4 with the signature in the `Authorization` header.
+struct SigV4Signer<'a> {
+    access_key: &'a str,
+    secret_key: &'a str,
+    session_token: Option<&'a str>,
+    region: &'a str,
+    service: &'a str,
+}
+
+impl<'a> SigV4Signer<'a> {
+    fn signing_key(&self, date: &str) -> Vec<u8> {
+        let secret = format!("AWS4{}", self.secret_key);
+        let k_date = hmac(secret.as_bytes(), date.as_bytes());
+        let k_region = hmac(&k_date, self.region.as_bytes());
+        let k_service = hmac(&k_region, self.service.as_bytes());
+        hmac(&k_service, b"aws4_request")
+    }
+
+    /// The signed header names and the signature of a request with the given path, as it is
+    /// sent, and canonical query. `amz_date` is the `x-amz-date` header, e.g. 20150830T123600Z.
+    fn signature(
+        &self,
+        method: &Method,
+        path: &str,
+        query: &str,
+        headers: &HeaderMap,
+        amz_date: &str,
+        payload_hash: &str,
+    ) -> HurlResult<(String, String)> {
+        // Every header is signed except the signature itself. Repeated headers are signed as a
+        // single comma separated value.
+        let mut canonical_headers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (name, value) in headers.iter() {
+            if name == AUTHORIZATION {
+                continue;
+            }
+            canonical_headers
+                .entry(name.as_str().to_owned())
+                .or_default()
+                .push(header_str(value)?.trim().to_owned());
+        }
+        let signed_headers = canonical_headers
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            canonical_path(path, self.service),
+            query,
+            canonical_headers
+                .iter()
+                .map(|(name, values)| format!("{}:{}\n", name, values.join(",")))
+                .collect::<String>(),
+            signed_headers,
+            payload_hash
+        );
+        trace!("SigV4 canonical request: {:?}", canonical_request);
+
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signature = hex::encode(hmac(&self.signing_key(date), string_to_sign.as_bytes()));
+        Ok((signed_headers, signature))
+    }
+}
+
+impl<'a> Signer for SigV4Signer<'a> {
+    fn sign(&self, request: &mut Request, payload: &Payload) -> HurlResult<()> {
+        let now: DateTime<Utc> = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = match payload {
+            Payload::Empty => hex::encode(Sha256::digest(b"")),
+            Payload::Bytes { bytes, .. } => hex::encode(Sha256::digest(bytes)),
+            Payload::Streamed => UNSIGNED_PAYLOAD.to_owned(),
+        };
+
+        let host = host(request.url())?;
+        if request.headers().contains_key(AUTHORIZATION) {
+            warn!("Replacing the Authorization header with the AWS signature");
+        }
+        {
+            let headers = request.headers_mut();
+            headers.insert(HOST, header_value(&host)?);
+            headers.insert("x-amz-date", header_value(&amz_date)?);
+            headers.insert("x-amz-content-sha256", header_value(&payload_hash)?);
+            if let Some(token) = self.session_token {
+                headers.insert("x-amz-security-token", header_value(token)?);
+            }
+        }
+
+        let (signed_headers, signature) = self.signature(
+            request.method(),
+            request.url().path(),
+            &canonical_query(request.url()),
+            request.headers(),
+            &amz_date,
+            &payload_hash,
+        )?;
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+
+        request.headers_mut().insert(
+            AUTHORIZATION,
+            header_value(&format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key, scope, signed_headers, signature
+            ))?,
+        );
+        Ok(())
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_varkey(key).expect("HMAC accepts keys of any length");
+    mac.input(data);
+    mac.result().code().to_vec()
+}
+
+fn header_value(s: &str) -> HurlResult<HeaderValue> {
+    HeaderValue::from_str(s).map_err(|_| Error::Signing(format!("invalid header value '{}'", s)))
+}
+
+fn header_str(value: &HeaderValue) -> HurlResult<&str> {
+    value
+        .to_str()
+        .map_err(|_| Error::Signing("cannot sign a header which is not visible ASCII".to_owned()))
+}
+
+fn host(url: &Url) -> HurlResult<String> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| Error::Signing(format!("URL {} has no host", url)))?;
+    Ok(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_owned(),
+    })
+}
+
+fn path_and_query(url: &Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned(),
+    }
+}
+
+/// The path with each segment encoded the way AWS expects. S3 signs the path as it is sent,
+/// while every other service signs it with each segment encoded a second time.
+fn canonical_path(path: &str, service: &str) -> String {
+    let path = path
+        .split('/')
+        .map(|segment| {
+            if service == "s3" {
+                aws_encode(&percent_decode(segment))
+            } else {
+                aws_encode(segment)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    if path.is_empty() {
+        "/".to_owned()
+    } else {
+        path
+    }
+}
+
+/// The query pairs encoded the way AWS expects and sorted by key and then value.
+fn canonical_query(url: &Url) -> String {
+    let mut pairs = url
+        .query_pairs()
+        .map(|(k, v)| (aws_encode(&k), aws_encode(&v)))
+        .collect::<Vec<_>>();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Percent encodes everything except the unreserved characters of RFC 3986.
+fn aws_encode(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                buf.push(b as char)
+            }
+            _ => buf.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    buf
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .and_then(|h| std::str::from_utf8(h).ok());
+            if let Some(b) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The credentials and request of AWS's SigV4 test suite.
+    const SUITE_SIGNER: SigV4Signer<'static> = SigV4Signer {
+        access_key: "AKIDEXAMPLE",
+        secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+        session_token: None,
+        region: "us-east-1",
+        service: "service",
+    };
+    const SUITE_DATE: &str = "20150830T123600Z";
+    const EMPTY_HASH: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    fn suite_signature(path: &str) -> (String, String) {
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, HeaderValue::from_static("example.amazonaws.com"));
+        headers.insert("x-amz-date", HeaderValue::from_static(SUITE_DATE));
+        SUITE_SIGNER
+            .signature(&Method::GET, path, "", &headers, SUITE_DATE, EMPTY_HASH)
+            .unwrap()
+    }
+
+    #[test]
+    fn sigv4_get_vanilla() {
+        assert_eq!(
+            suite_signature("/"),
+            (
+                "host;x-amz-date".to_owned(),
+                "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn sigv4_get_space() {
+        // The suite sends the path unencoded, as `GET /example space/`.
+        assert_eq!(
+            suite_signature("/example space/").1,
+            "652487583200325589f1fba4c7e578f72c47cb61beeca81406b39ddec1366741"
+        );
+    }
+
+    #[test]
+    fn sigv4_signing_key() {
+        let signer = SigV4Signer {
+            service: "iam",
+            ..SUITE_SIGNER
+        };
+        assert_eq!(
+            hex::encode(signer.signing_key("20120215")),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn only_s3_paths_are_encoded_once() {
+        let url = Url::parse("https://example.com/a b/c:d%25e").unwrap();
+        assert_eq!(url.path(), "/a%20b/c:d%25e");
+        assert_eq!(canonical_path(url.path(), "s3"), "/a%20b/c%3Ad%25e");
+        assert_eq!(
+            canonical_path(url.path(), "execute-api"),
+            "/a%2520b/c%3Ad%2525e"
+        );
+        assert_eq!(canonical_path("", "es"), "/");
+    }
+
+    #[test]
+    fn hmac_signs_method_path_date_and_body() {
+        let signer = HmacSigner {
+            key_id: "key",
+            secret: "secret",
+            header: "authorization",
+        };
+        let url = Url::parse("https://api.example.com/orders?id=1").unwrap();
+        let mut request = Request::new(Method::POST, url);
+        request.headers_mut().insert(
+            DATE,
+            HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT"),
+        );
+        let payload = Payload::Bytes {
+            content_type: "application/json",
+            bytes: b"{\"a\":1}".to_vec(),
+        };
+        signer.sign(&mut request, &payload).unwrap();
+
+        let headers = request.headers();
+        assert_eq!(
+            headers["x-content-sha256"],
+            "015abd7f5cc57a2dd94b7590f04ad8084273905ee33ec5cebeae62276a97f862"
+        );
+        assert_eq!(
+            headers[AUTHORIZATION],
+            "HMAC-SHA256 KeyId=key, \
+             Signature=e5c47b922371b7696f57eae8af56f318cc8e7ed74edb02bdae54e7cba36558b6"
+        );
+    }
+
+    #[test]
+    fn hmac_refuses_streamed_bodies() {
+        let signer = HmacSigner {
+            key_id: "key",
+            secret: "secret",
+            header: "authorization",
+        };
+        let url = Url::parse("https://api.example.com/upload").unwrap();
+        let mut request = Request::new(Method::POST, url);
+        assert!(matches!(
+            signer.sign(&mut request, &Payload::Streamed),
+            Err(Error::Signing(_))
+        ));
+    }
+}
@@ -0,0 +1,400 @@
+use crate::app::App;
+use crate::client::Payload;
+use crate::errors::{Error, HurlResult};
+use log::{debug, trace};
+use reqwest::header::{HeaderValue, ACCEPT, CONNECTION, CONTENT_LENGTH, HOST, TRANSFER_ENCODING};
+use reqwest::{Proxy, Request, Response, Url, Version};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// The most headers we will parse from a response received over a Unix socket.
+const MAX_HEADERS: usize = 100;
+
+/// An override of the address used for a host and port, given as `host:port:address`.
+///
+/// The request still names the original host in its `Host` header, which is what lets us reach
+/// a particular backend behind a load balancer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Resolve {
+    host: String,
+    port: u16,
+    addr: IpAddr,
+}
+
+impl FromStr for Resolve {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidResolve(s.to_owned());
+        let mut parts = s.splitn(3, ':');
+        let host = parts.next().filter(|h| !h.is_empty()).ok_or_else(invalid)?;
+        let port = parts
+            .next()
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(invalid)?;
+        let addr = parts
+            .next()
+            .map(|a| a.trim_start_matches('[').trim_end_matches(']'))
+            .and_then(|a| a.parse().ok())
+            .ok_or_else(invalid)?;
+        Ok(Resolve {
+            host: host.to_ascii_lowercase(),
+            port,
+            addr,
+        })
+    }
+}
+
+/// The first override for the host and port of `url`, if any.
+pub fn find<'r>(overrides: &'r [Resolve], url: &Url) -> Option<&'r Resolve> {
+    let host = url.host_str()?.to_ascii_lowercase();
+    let port = url.port_or_known_default()?;
+    find_host(overrides, &host, port)
+}
+
+fn find_host<'r>(overrides: &'r [Resolve], host: &str, port: u16) -> Option<&'r Resolve> {
+    overrides.iter().find(|r| r.host == host && r.port == port)
+}
+
+impl Resolve {
+    /// Points the request at our address, keeping the original host in the `Host` header.
+    ///
+    /// This is done after signing, since signatures cover the host the user asked for. It is
+    /// only used for plain http, as https goes through `tunnel` instead.
+    pub fn apply(&self, request: &mut Request) -> HurlResult<()> {
+        debug!("Resolving {}:{} to {}", self.host, self.port, self.addr);
+        if !request.headers().contains_key(HOST) {
+            let value = host(request.url());
+            let value = HeaderValue::from_str(&value).map_err(|_| Error::InvalidResolve(value))?;
+            request.headers_mut().insert(HOST, value);
+        }
+        request
+            .url_mut()
+            .set_ip_host(self.addr)
+            .map_err(|_| Error::InvalidResolve(self.host.clone()))
+    }
+}
+
+/// Starts a proxy on a local port which tunnels https to the addresses given with --resolve.
+///
+/// reqwest cannot connect to one address while checking the certificate against another name,
+/// but it does tunnel https through a proxy with the URL left as it is. The TLS handshake,
+/// hostname check included, is therefore made with the host the user asked for, whichever TLS
+/// backend is in use, and only the TCP connection goes to the overriding address.
+///
+/// Only https requests to a host with an override are sent through the proxy, and since any
+/// local process could connect to it, it refuses to tunnel anywhere else. It stops once the
+/// client the returned `Proxy` is given to has been dropped.
+pub fn tunnel(overrides: &[Resolve]) -> HurlResult<Proxy> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let local = listener.local_addr()?;
+    trace!("Tunnelling https through {}", local);
+    let proxy = format!("http://{}", local);
+    let tunnel = Arc::new(Tunnel {
+        local,
+        proxy: Url::parse(&proxy).map_err(|source| Error::UrlParseError { url: proxy, source })?,
+        overrides: overrides.to_vec(),
+        stopped: Arc::new(AtomicBool::new(false)),
+    });
+    let stopped = tunnel.stopped.clone();
+    let overrides = tunnel.overrides.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            let overrides = overrides.clone();
+            match stream {
+                Ok(stream) => {
+                    thread::spawn(move || {
+                        if let Err(e) = serve_tunnel(stream, &overrides) {
+                            debug!("Tunnel failed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => debug!("Could not accept a tunnel connection: {}", e),
+            }
+        }
+        trace!("Stopped tunnelling through {}", local);
+    });
+    Ok(Proxy::custom(move |url| tunnel.proxy_for(url)))
+}
+
+// The running tunnel, which the proxy of the client owns.
+struct Tunnel {
+    local: SocketAddr,
+    proxy: Url,
+    overrides: Vec<Resolve>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Tunnel {
+    fn proxy_for(&self, url: &Url) -> Option<Url> {
+        if url.scheme() == "https" && find(&self.overrides, url).is_some() {
+            Some(self.proxy.clone())
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for Tunnel {
+    // The listener only looks at the flag when a connection arrives, so it is woken with one.
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        let _ = TcpStream::connect(self.local);
+    }
+}
+
+// Answers one `CONNECT host:port` and then copies bytes both ways until the two sides hang up.
+fn serve_tunnel(mut client: TcpStream, overrides: &[Resolve]) -> io::Result<()> {
+    let mut reader = BufReader::new(client.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+    let target = request_line
+        .strip_prefix("CONNECT ")
+        .and_then(|rest| rest.split_whitespace().next());
+    let upstream = match target.map(|target| connect(target, overrides)) {
+        Some(Ok(upstream)) => upstream,
+        Some(Err(e)) if e.kind() == io::ErrorKind::PermissionDenied => {
+            client.write_all(b"HTTP/1.1 403 Forbidden\r\n\r\n")?;
+            return Err(e);
+        }
+        Some(Err(e)) => {
+            client.write_all(b"HTTP/1.1 502 Bad Gateway\r\n\r\n")?;
+            return Err(e);
+        }
+        None => return client.write_all(b"HTTP/1.1 405 Method Not Allowed\r\n\r\n"),
+    };
+    client.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")?;
+
+    let mut to_upstream = upstream.try_clone()?;
+    to_upstream.write_all(reader.buffer())?;
+    let mut from_client = reader.into_inner();
+    let mut from_upstream = upstream;
+    let to_client = thread::spawn(move || {
+        let copied = io::copy(&mut from_upstream, &mut client);
+        let _ = client.shutdown(Shutdown::Write);
+        copied
+    });
+    io::copy(&mut from_client, &mut to_upstream)?;
+    let _ = to_upstream.shutdown(Shutdown::Write);
+    if let Ok(copied) = to_client.join() {
+        copied?;
+    }
+    Ok(())
+}
+
+fn connect(target: &str, overrides: &[Resolve]) -> io::Result<TcpStream> {
+    let mut parts = target.rsplitn(2, ':');
+    let port = parts.next().and_then(|p| p.parse().ok());
+    let host = parts.next().map(|h| {
+        h.trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_ascii_lowercase()
+    });
+    match (host, port) {
+        (Some(host), Some(port)) => match find_host(overrides, &host, port) {
+            Some(resolve) => {
+                debug!("Resolving {}:{} to {}", host, port, resolve.addr);
+                TcpStream::connect((resolve.addr, port))
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} has no --resolve override", target),
+            )),
+        },
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid tunnel target {}", target),
+        )),
+    }
+}
+
+/// Sends a request over a Unix domain socket and reads the whole response.
+///
+/// reqwest only speaks TCP, so we write the request as HTTP/1.1 ourselves with
+/// `Connection: close` and read until the server hangs up. Multipart bodies are streamed by
+/// reqwest and cannot be sent this way.
+#[cfg(unix)]
+pub fn send_unix(path: &Path, request: &Request, payload: &Payload) -> HurlResult<Response> {
+    use std::os::unix::net::UnixStream;
+
+    let socket_error = |source| Error::Socket {
+        path: path.to_owned(),
+        source,
+    };
+    let body: &[u8] = match payload {
+        Payload::Empty => &[],
+        Payload::Bytes { bytes, .. } => bytes,
+        Payload::Streamed => return Err(Error::StreamedBodyOverSocket),
+    };
+
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\n",
+        request.method(),
+        path_and_query(request.url())
+    );
+    let headers = request.headers();
+    if !headers.contains_key(HOST) {
+        head.push_str(&format!("host: {}\r\n", host(request.url())));
+    }
+    if !headers.contains_key(ACCEPT) {
+        head.push_str("accept: */*\r\n");
+    }
+    for (name, value) in headers.iter() {
+        if name == CONNECTION || name == CONTENT_LENGTH {
+            continue;
+        }
+        head.push_str(&format!("{}: ", name));
+        head.push_str(&String::from_utf8_lossy(value.as_bytes()));
+        head.push_str("\r\n");
+    }
+    if !body.is_empty() {
+        head.push_str(&format!("content-length: {}\r\n", body.len()));
+    }
+    head.push_str("connection: close\r\n\r\n");
+    trace!("Request head over {}: {:?}", path.display(), head);
+
+    let mut stream = UnixStream::connect(path).map_err(socket_error)?;
+    stream
+        .write_all(head.as_bytes())
+        .and_then(|_| stream.write_all(body))
+        .map_err(socket_error)?;
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).map_err(socket_error)?;
+    parse_response(&raw)
+}
+
+#[cfg(not(unix))]
+pub fn send_unix(path: &Path, _request: &Request, _payload: &Payload) -> HurlResult<Response> {
+    Err(Error::Socket {
+        path: path.to_owned(),
+        source: std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "Unix sockets are not supported on this platform",
+        ),
+    })
+}
+
+fn parse_response(raw: &[u8]) -> HurlResult<Response> {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut parsed = httparse::Response::new(&mut headers);
+    let head_len = match parsed.parse(raw) {
+        Ok(httparse::Status::Complete(len)) => len,
+        Ok(httparse::Status::Partial) => {
+            return Err(Error::MalformedResponse(
+                "the connection closed before the headers were complete".to_owned(),
+            ))
+        }
+        Err(e) => return Err(Error::MalformedResponse(e.to_string())),
+    };
+
+    let mut builder = http::Response::builder();
+    builder
+        .status(parsed.code.unwrap_or(200))
+        .version(if parsed.version == Some(0) {
+            Version::HTTP_10
+        } else {
+            Version::HTTP_11
+        });
+    let mut chunked = false;
+    let mut content_length = None;
+    for header in parsed.headers.iter() {
+        if header.name.eq_ignore_ascii_case(TRANSFER_ENCODING.as_str()) {
+            chunked = String::from_utf8_lossy(header.value)
+                .to_ascii_lowercase()
+                .contains("chunked");
+        } else if header.name.eq_ignore_ascii_case(CONTENT_LENGTH.as_str()) {
+            content_length = std::str::from_utf8(header.value)
+                .ok()
+                .and_then(|v| v.trim().parse::<usize>().ok());
+        }
+        builder.header(header.name, header.value);
+    }
+
+    let rest = &raw[head_len..];
+    let body = if chunked {
+        decode_chunked(rest)?
+    } else if let Some(len) = content_length {
+        rest[..len.min(rest.len())].to_vec()
+    } else {
+        rest.to_vec()
+    };
+    builder
+        .body(body)
+        .map(Response::from)
+        .map_err(|e| Error::MalformedResponse(e.to_string()))
+}
+
+fn decode_chunked(mut raw: &[u8]) -> HurlResult<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = raw
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| Error::MalformedResponse("truncated chunk size".to_owned()))?;
+        let size = std::str::from_utf8(&raw[..line_end])
+            .ok()
+            .and_then(|line| line.split(';').next())
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or_else(|| Error::MalformedResponse("invalid chunk size".to_owned()))?;
+        raw = &raw[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        if raw.len() < size {
+            return Err(Error::MalformedResponse("truncated chunk".to_owned()));
+        }
+        body.extend_from_slice(&raw[..size]);
+        raw = raw.get(size + 2..).unwrap_or(&[]);
+    }
+}
+
+/// Describes the protocol a response was received with, for verbose output.
+///
+/// reqwest does not expose the TLS session, so the ALPN result is worked out from the version
+/// of the response and what we offered: rustls offers h2 and http/1.1, or only h2 with prior
+/// knowledge, while the default backend offers nothing.
+pub fn describe_protocol(app: &App, url: &Url, version: Version) -> String {
+    let h2 = version == Version::HTTP_2;
+    if app.unix_socket.is_some() {
+        return format!("{:?} over a Unix socket", version);
+    }
+    match (url.scheme(), h2) {
+        ("https", true) => "h2, negotiated with ALPN".to_owned(),
+        ("https", false) if app.http2 => {
+            format!("{:?}, the server did not select h2 with ALPN", version)
+        }
+        ("https", false) => format!("{:?}, no ALPN offered", version),
+        (_, true) => "h2c with prior knowledge".to_owned(),
+        (_, false) => format!("{:?} in cleartext", version),
+    }
+}
+
+fn path_and_query(url: &Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned(),
+    }
+}
+
+fn host(url: &Url) -> String {
+    match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_owned(),
+        (None, _) => "localhost".to_owned(),
+    }
+}
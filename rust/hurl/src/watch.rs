@@ -0,0 +1,436 @@
+use crate::app::{App, Parameter};
+use crate::client;
+use crate::diff::{self, Change, Diff, Snapshot};
+use crate::encoding;
+use crate::errors::{Error, HurlResult};
+use crate::schema::escape;
+use crate::session::Session;
+use chrono::Local;
+use reqwest::Client;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::Theme;
+use syntect::parsing::SyntaxSet;
+use syntect::util::as_24_bit_terminal_escaped;
+
+/// Moves the cursor to the top left corner and clears the terminal.
+const CLEAR_SCREEN: &str = "\x1b[H\x1b[2J";
+
+const ADDED: &str = "\x1b[32m+\x1b[0m ";
+const CHANGED: &str = "\x1b[33m~\x1b[0m ";
+const UNCHANGED: &str = "  ";
+
+/// How long to wait between polls, e.g. `5s`, `500ms`, `2m` or `1h`. A bare number is seconds.
+#[derive(Debug, Clone, Copy)]
+pub struct Interval(pub Duration);
+
+impl FromStr for Interval {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let invalid = || Error::InvalidDuration(s.to_owned());
+        let n = number.parse::<u64>().map_err(|_| invalid())?;
+        let seconds = |factor: u64| n.checked_mul(factor).map(Duration::from_secs);
+        let duration = match unit {
+            "ms" => Some(Duration::from_millis(n)),
+            "" | "s" => Some(Duration::from_secs(n)),
+            "m" => seconds(60),
+            "h" => seconds(60 * 60),
+            _ => None,
+        }
+        .ok_or_else(invalid)?;
+        if duration == Duration::from_secs(0) {
+            return Err(invalid());
+        }
+        Ok(Interval(duration))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// A condition on a response which ends watch mode, e.g. `json.state == "done"`.
+///
+/// The left hand side is `status`, `headers` or `json` followed by any number of `.key`,
+/// `["key"]` or `[index]` accessors. The right hand side is a JSON literal. Without an operator
+/// the condition holds if the value is present and not `false`, `0` or `""`.
+#[derive(Debug)]
+pub struct Condition {
+    source: String,
+    root: String,
+    path: Vec<Segment>,
+    comparison: Option<(Op, Value)>,
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |message: &str| Error::InvalidCondition {
+            expr: s.to_owned(),
+            message: message.to_owned(),
+        };
+        let chars: Vec<char> = s.trim().chars().collect();
+        let mut i = 0;
+
+        let ident = |i: &mut usize| {
+            let start = *i;
+            while *i < chars.len()
+                && (chars[*i].is_alphanumeric() || chars[*i] == '_' || chars[*i] == '-')
+            {
+                *i += 1;
+            }
+            chars[start..*i].iter().collect::<String>()
+        };
+
+        let root = ident(&mut i);
+        if !["status", "headers", "json"].contains(&root.as_str()) {
+            return Err(invalid("expected status, headers or json"));
+        }
+        let mut path = Vec::new();
+        loop {
+            match chars.get(i) {
+                Some('.') => {
+                    i += 1;
+                    let key = ident(&mut i);
+                    if key.is_empty() {
+                        return Err(invalid("expected a key after `.`"));
+                    }
+                    path.push(Segment::Key(key));
+                }
+                Some('[') => {
+                    let end = match chars[i..].iter().position(|c| *c == ']') {
+                        Some(end) => i + end,
+                        None => return Err(invalid("missing `]`")),
+                    };
+                    let inner: String = chars[i + 1..end].iter().collect();
+                    let segment = match serde_json::from_str(inner.trim()) {
+                        Ok(Value::String(key)) => Segment::Key(key),
+                        Ok(Value::Number(ref n)) if n.is_u64() => {
+                            Segment::Index(n.as_u64().unwrap() as usize)
+                        }
+                        _ => return Err(invalid("expected an index or a quoted key in `[]`")),
+                    };
+                    path.push(segment);
+                    i = end + 1;
+                }
+                _ => break,
+            }
+        }
+
+        let rest: String = chars[i..].iter().collect();
+        let rest = rest.trim();
+        if rest.is_empty() {
+            return Ok(Condition {
+                source: s.to_owned(),
+                root,
+                path,
+                comparison: None,
+            });
+        }
+        let ops = [
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        let (token, op) = ops
+            .iter()
+            .find(|(token, _)| rest.starts_with(token))
+            .ok_or_else(|| invalid("expected one of ==, !=, <, <=, > or >="))?;
+        let value = serde_json::from_str(rest[token.len()..].trim())
+            .map_err(|_| invalid("the right hand side must be a JSON value, e.g. \"done\""))?;
+        Ok(Condition {
+            source: s.to_owned(),
+            root,
+            path,
+            comparison: Some((*op, value)),
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Condition {
+    pub fn is_met(&self, snapshot: &Snapshot) -> bool {
+        let root = match self.root.as_str() {
+            "status" => Value::from(snapshot.status),
+            "headers" => Value::Object(
+                snapshot
+                    .headers
+                    .iter()
+                    .map(|(k, v)| (k.clone(), diff::header_value(v)))
+                    .collect(),
+            ),
+            _ => snapshot.body.clone(),
+        };
+        let mut value = &root;
+        for segment in self.path.iter() {
+            let next = match segment {
+                // Header names are stored in lower case.
+                Segment::Key(key) if self.root == "headers" => value.get(key.to_lowercase()),
+                Segment::Key(key) => value.get(key),
+                Segment::Index(index) => value.get(index),
+            };
+            value = next.unwrap_or(&Value::Null);
+        }
+
+        match &self.comparison {
+            None => match value {
+                Value::Null | Value::Bool(false) => false,
+                Value::Number(n) => n.as_f64() != Some(0.0),
+                Value::String(s) => !s.is_empty(),
+                _ => true,
+            },
+            Some((op, expected)) => {
+                let ordering = match (value, expected) {
+                    (Value::Number(a), Value::Number(b)) => a
+                        .as_f64()
+                        .and_then(|a| b.as_f64().and_then(|b| a.partial_cmp(&b))),
+                    (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                    (a, b) if a == b => Some(Ordering::Equal),
+                    _ => None,
+                };
+                match op {
+                    Op::Eq => ordering == Some(Ordering::Equal),
+                    Op::Ne => ordering != Some(Ordering::Equal),
+                    Op::Lt => ordering == Some(Ordering::Less),
+                    Op::Le => matches!(ordering, Some(Ordering::Less) | Some(Ordering::Equal)),
+                    Op::Gt => ordering == Some(Ordering::Greater),
+                    Op::Ge => matches!(ordering, Some(Ordering::Greater) | Some(Ordering::Equal)),
+                }
+            }
+        }
+    }
+}
+
+/// Issues the request every `interval` and redraws the response in place, marking what changed
+/// since the previous poll. Stops once `--until` is satisfied, if it was given.
+///
+/// A failed poll is shown in place of the response and does not stop watching, since the
+/// service being watched is often restarting.
+pub fn run(
+    app: &App,
+    ss: &SyntaxSet,
+    theme: &Theme,
+    method: reqwest::Method,
+    url: &str,
+    parameters: &Vec<Parameter>,
+    session: &mut Option<Session>,
+) -> HurlResult<()> {
+    let interval = app.watch.map_or(Duration::from_secs(1), |i| i.0);
+    let mut previous: Option<Snapshot> = None;
+    let mut count = 0;
+    let client = client::build_client(app)?;
+    loop {
+        count += 1;
+        // Only the first poll is recorded, so that watching does not fill the history.
+        let result = poll(
+            app,
+            &client,
+            method.clone(),
+            url,
+            parameters,
+            session,
+            count == 1,
+        );
+
+        print!("{}", CLEAR_SCREEN);
+        println!(
+            "Every {:?}: {} {}, poll {} at {}",
+            interval,
+            method,
+            url,
+            count,
+            Local::now().format("%H:%M:%S")
+        );
+        if let Some(until) = &app.until {
+            println!("Until: {}", until);
+        }
+        println!();
+        match result {
+            Ok((version, snapshot)) => {
+                let changes = previous
+                    .as_ref()
+                    .map(|p| diff::diff(p, &snapshot, &app.ignore));
+                render(ss, theme, version, &snapshot, changes.as_ref());
+                if app.until.as_ref().filter(|c| c.is_met(&snapshot)).is_some() {
+                    println!("Condition met after {} poll(s)", count);
+                    return Ok(());
+                }
+                previous = Some(snapshot);
+            }
+            Err(e) => println!("\x1b[31m{:?}\x1b[0m", e),
+        }
+        io::stdout().flush()?;
+        thread::sleep(interval);
+    }
+}
+
+fn poll(
+    app: &App,
+    client: &Client,
+    method: reqwest::Method,
+    url: &str,
+    parameters: &Vec<Parameter>,
+    session: &mut Option<Session>,
+    record: bool,
+) -> HurlResult<(reqwest::Version, Snapshot)> {
+    let exchange = if record {
+        client::perform_recorded(app, client, method, session, url, parameters)?
+    } else {
+        client::perform(app, client, method, session, url, parameters)?
+    };
+    let mut resp = exchange.response;
+    let body = encoding::read_body(&mut resp)?.text;
+    if !app.read_only {
+        if let Some(s) = session {
+            s.update_with_response(&resp);
+            s.save(app)?;
+        }
+    }
+    Ok((resp.version(), Snapshot::from_response(&resp, &body)))
+}
+
+fn render(
+    ss: &SyntaxSet,
+    theme: &Theme,
+    version: reqwest::Version,
+    snapshot: &Snapshot,
+    changes: Option<&Diff>,
+) {
+    let status = reqwest::StatusCode::from_u16(snapshot.status)
+        .ok()
+        .and_then(|s| s.canonical_reason())
+        .unwrap_or("Unknown");
+    let mark = match changes {
+        Some(d) if d.status.is_some() => CHANGED,
+        _ => UNCHANGED,
+    };
+    let status_line = format!("{:?} {} {}", version, snapshot.status, status);
+    highlight(ss, theme, "HTTP", &[(mark, status_line)]);
+    println!();
+
+    let body_changes: &[(String, Change)] = changes.map_or(&[], |d| &d.body);
+    let change_at = |pointer: &str| {
+        body_changes
+            .iter()
+            .find(|(p, _)| pointer == p || pointer.starts_with(&format!("{}/", p)))
+            .map(|(_, c)| c)
+    };
+    match &snapshot.body {
+        Value::String(text) => {
+            let mark = gutter(change_at(""));
+            for line in text.lines() {
+                println!("{}{}", mark, line);
+            }
+        }
+        body => {
+            let lines: Vec<(&str, String)> = pretty_lines(body)
+                .into_iter()
+                .map(|(pointer, line)| (gutter(change_at(&pointer)), line))
+                .collect();
+            highlight(ss, theme, "JSON", &lines);
+        }
+    }
+    for (pointer, change) in body_changes.iter() {
+        if let Change::Removed(v) = change {
+            println!("\x1b[31m- {}: {}\x1b[0m", pointer, v);
+        }
+    }
+}
+
+/// The marker shown to the left of a line, depending on how it changed since the previous poll.
+fn gutter(change: Option<&Change>) -> &'static str {
+    match change {
+        Some(Change::Added(_)) => ADDED,
+        Some(_) => CHANGED,
+        None => UNCHANGED,
+    }
+}
+
+fn highlight(ss: &SyntaxSet, theme: &Theme, syntax: &str, lines: &[(&str, String)]) {
+    let syn = ss
+        .find_syntax_by_name(syntax)
+        .unwrap_or_else(|| panic!("{} syntax should exist", syntax));
+    let mut h = HighlightLines::new(syn, theme);
+    for (mark, line) in lines.iter() {
+        let line = format!("{}\n", line);
+        let regions = h.highlight(&line, ss);
+        print!(
+            "{}{}",
+            mark,
+            as_24_bit_terminal_escaped(&regions[..], false)
+        );
+    }
+    print!("\x1b[0m");
+}
+
+/// Pretty prints a value the way `serde_json::to_string_pretty` does, along with the JSON
+/// pointer of the value each line belongs to.
+fn pretty_lines(value: &Value) -> Vec<(String, String)> {
+    let mut lines = Vec::new();
+    push_lines(value, String::new(), String::new(), 0, false, &mut lines);
+    lines
+}
+
+fn push_lines(
+    value: &Value,
+    pointer: String,
+    prefix: String,
+    indent: usize,
+    comma: bool,
+    lines: &mut Vec<(String, String)>,
+) {
+    let comma = if comma { "," } else { "" };
+    let close_indent = "  ".repeat(indent);
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            lines.push((pointer.clone(), format!("{}{{", prefix)));
+            for (i, (key, v)) in map.iter().enumerate() {
+                let prefix = format!("{}  {}: ", close_indent, Value::String(key.clone()));
+                let child = format!("{}/{}", pointer, escape(key));
+                push_lines(v, child, prefix, indent + 1, i + 1 < map.len(), lines);
+            }
+            lines.push((pointer, format!("{}}}{}", close_indent, comma)));
+        }
+        Value::Array(items) if !items.is_empty() => {
+            lines.push((pointer.clone(), format!("{}[", prefix)));
+            for (i, v) in items.iter().enumerate() {
+                let prefix = format!("{}  ", close_indent);
+                let child = format!("{}/{}", pointer, i);
+                push_lines(v, child, prefix, indent + 1, i + 1 < items.len(), lines);
+            }
+            lines.push((pointer, format!("{}]{}", close_indent, comma)));
+        }
+        _ => lines.push((pointer, format!("{}{}{}", prefix, value, comma))),
+    }
+}